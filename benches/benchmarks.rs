@@ -4,7 +4,11 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use num_derive::{FromPrimitive, ToPrimitive};
 use strum_macros::{Display, EnumCount};
 
-use tokidator::rbac::json_discriminant_array_to_vec;
+use tokidator::crypto::{PrivateKey, PublicKey};
+use tokidator::message::{SignedMessage, SignedMessageRef};
+use tokidator::rbac::PolicyCond::Contains;
+use tokidator::rbac::{json_discriminant_array_to_vec, PolicySet, PolicySetRef};
+use tokidator::token::{PolicyAccessToken, PolicyAccessTokenRef, ValidationAuthority};
 
 #[derive(
     Copy,
@@ -38,6 +42,66 @@ pub enum TestPolicy {
     Policy15,
 }
 
+impl tokidator::rbac::Policy for TestPolicy {}
+
+/// Access token encoded as an expiration flag followed by the policies
+pub struct BenchAccessToken {
+    policies: PolicySet<TestPolicy>,
+    expired: bool,
+}
+
+impl PolicyAccessToken for BenchAccessToken {
+    type Policy = TestPolicy;
+
+    fn policies(&self) -> &PolicySet<Self::Policy> {
+        &self.policies
+    }
+
+    /// Expired tokens expired at the epoch, the others never expire
+    fn expires_at(&self) -> Option<u64> {
+        Some(if self.expired { 0 } else { u64::MAX })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.expired as u8];
+        bytes.extend(self.policies.to_bytes());
+        bytes
+    }
+
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        let (expired, policies) = buf.split_first()?;
+        Some(Self {
+            policies: PolicySet::parse_from_bytes(policies).ok()?,
+            expired: *expired != 0,
+        })
+    }
+}
+
+pub struct BenchAccessTokenRef<'a> {
+    policies: &'a [u8],
+    expired: bool,
+}
+
+impl<'a> PolicyAccessTokenRef<'a> for BenchAccessTokenRef<'a> {
+    type Policy = TestPolicy;
+
+    fn policies(&self) -> PolicySetRef<'a, Self::Policy> {
+        PolicySetRef::from_bytes(self.policies)
+    }
+
+    fn expires_at(&self) -> Option<u64> {
+        Some(if self.expired { 0 } else { u64::MAX })
+    }
+
+    fn from_bytes(buf: &'a [u8]) -> Option<Self> {
+        let (expired, policies) = buf.split_first()?;
+        Some(Self {
+            policies,
+            expired: *expired != 0,
+        })
+    }
+}
+
 pub fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("json_discriminant_array_to_vec", |b| {
         b.iter(|| {
@@ -46,5 +110,56 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, criterion_benchmark);
+/// Owned decode path against the borrowed one of `SignedMessageRef`
+pub fn decode_benchmark(c: &mut Criterion) {
+    let private_key = PrivateKey::generate();
+    let public_key: PublicKey = private_key.public_key();
+    let access_token = BenchAccessToken {
+        policies: vec![TestPolicy::Policy1, TestPolicy::Policy9].into(),
+        expired: false,
+    };
+    let token = SignedMessage::create_with_key_id(
+        access_token.to_bytes(),
+        &private_key,
+        private_key.thumbprint(),
+    )
+    .unwrap()
+    .encode();
+
+    c.bench_function("signed_message_decode", |b| {
+        b.iter(|| SignedMessage::decode(black_box(&token)).unwrap())
+    });
+    c.bench_function("signed_message_ref_decode", |b| {
+        b.iter(|| {
+            let mut buffer = [0u8; 256];
+            SignedMessageRef::decode(black_box(&token), &mut buffer)
+                .unwrap()
+                .message()
+                .len()
+        })
+    });
+
+    let va = ValidationAuthority::<BenchAccessToken>::new(public_key);
+    let condition = Contains(TestPolicy::Policy9);
+    c.bench_function("enforce", |b| {
+        b.iter(|| {
+            va.enforce(&condition, Some(black_box(token.as_str())))
+                .unwrap()
+        })
+    });
+    c.bench_function("enforce_ref", |b| {
+        b.iter(|| {
+            let mut buffer = [0u8; 256];
+            va.enforce_ref::<BenchAccessTokenRef>(
+                &condition,
+                Some(black_box(token.as_str())),
+                &mut buffer,
+            )
+            .unwrap()
+            .expired
+        })
+    });
+}
+
+criterion_group!(benches, criterion_benchmark, decode_benchmark);
 criterion_main!(benches);
@@ -3,8 +3,8 @@ use protobuf_codegen_pure::{Codegen, Customize};
 fn main() {
     if let Err(e) = Codegen::new()
         .out_dir("src/protos")
-        .inputs(&["protos/token.proto"])
-        .includes(&["protos"])
+        .inputs(["protos/token.proto"])
+        .includes(["protos"])
         .customize(Customize {
             ..Default::default()
         })
@@ -0,0 +1,322 @@
+//! Minimal CBOR (RFC 8949) covering the structures of COSE and CWT
+//!
+//! Only definite lengths, integers within `i64` and the simple values `false`, `true` and `null`
+//! are supported. Encoding always uses the shortest form of every length and integer.
+
+pub const MAJOR_UNSIGNED: u8 = 0;
+pub const MAJOR_NEGATIVE: u8 = 1;
+pub const MAJOR_BYTES: u8 = 2;
+pub const MAJOR_TEXT: u8 = 3;
+pub const MAJOR_ARRAY: u8 = 4;
+pub const MAJOR_MAP: u8 = 5;
+pub const MAJOR_TAG: u8 = 6;
+pub const MAJOR_SIMPLE: u8 = 7;
+const SIMPLE_FALSE: u64 = 20;
+const SIMPLE_TRUE: u64 = 21;
+const SIMPLE_NULL: u64 = 22;
+/// Nesting of COSE and CWT structures is shallow, anything deeper is rejected
+const MAX_DEPTH: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    /// Entries in encoding order, keys are unique
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Integer(n) if *n >= 0 => write_head(MAJOR_UNSIGNED, *n as u64, out),
+            // -1 - n without overflow
+            Value::Integer(n) => write_head(MAJOR_NEGATIVE, !*n as u64, out),
+            Value::Bytes(bytes) => {
+                write_head(MAJOR_BYTES, bytes.len() as u64, out);
+                out.extend_from_slice(bytes);
+            }
+            Value::Text(text) => {
+                write_head(MAJOR_TEXT, text.len() as u64, out);
+                out.extend_from_slice(text.as_bytes());
+            }
+            Value::Array(items) => {
+                write_head(MAJOR_ARRAY, items.len() as u64, out);
+                items.iter().for_each(|item| item.write(out));
+            }
+            Value::Map(entries) => {
+                write_head(MAJOR_MAP, entries.len() as u64, out);
+                for (key, value) in entries {
+                    key.write(out);
+                    value.write(out);
+                }
+            }
+            Value::Tag(tag, value) => {
+                write_head(MAJOR_TAG, *tag, out);
+                value.write(out);
+            }
+            Value::Bool(false) => write_head(MAJOR_SIMPLE, SIMPLE_FALSE, out),
+            Value::Bool(true) => write_head(MAJOR_SIMPLE, SIMPLE_TRUE, out),
+            Value::Null => write_head(MAJOR_SIMPLE, SIMPLE_NULL, out),
+        }
+    }
+
+    /// Decode a single value which must span the whole `input`
+    pub fn decode(input: &[u8]) -> Option<Self> {
+        let mut reader = Reader(input);
+        let value = reader.read(0)?;
+        if reader.0.is_empty() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Value of integer `label` in a map
+    pub fn get(&self, label: i64) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(key, _)| *key == Value::Integer(label))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+fn write_head(major: u8, n: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    if n < 24 {
+        out.push(major | n as u8);
+    } else if n <= u8::MAX as u64 {
+        out.extend_from_slice(&[major | 24, n as u8]);
+    } else if n <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(n as u16).to_be_bytes());
+    } else if n <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(n as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+/// Reader of encoded items, which are either decoded into a `Value` or skipped
+pub struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self(input)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    /// Read major type and argument of the next item
+    pub fn read_head(&mut self) -> Option<(u8, u64)> {
+        let initial = self.take(1)?[0];
+        let argument = match initial & 0x1f {
+            n @ 0..=23 => n as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => self.take(2)?.iter().fold(0, |n, b| n << 8 | *b as u64),
+            26 => self.take(4)?.iter().fold(0, |n, b| n << 8 | *b as u64),
+            27 => self.take(8)?.iter().fold(0, |n, b| n << 8 | *b as u64),
+            // indefinite lengths and reserved values
+            _ => return None,
+        };
+        Some((initial >> 5, argument))
+    }
+
+    /// Length of a string or container, every item takes at least a byte of the remaining input
+    fn read_len(&self, n: u64) -> Option<usize> {
+        if n > self.0.len() as u64 {
+            None
+        } else {
+            Some(n as usize)
+        }
+    }
+
+    /// Skip the next item without allocating, with the checks of `read` but those of map keys
+    pub fn skip(&mut self, depth: usize) -> Option<()> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let (major, n) = self.read_head()?;
+        match major {
+            MAJOR_UNSIGNED | MAJOR_NEGATIVE => (),
+            MAJOR_BYTES | MAJOR_TEXT => {
+                let len = self.read_len(n)?;
+                self.take(len)?;
+            }
+            MAJOR_ARRAY => {
+                for _ in 0..self.read_len(n)? {
+                    self.skip(depth + 1)?;
+                }
+            }
+            MAJOR_MAP => {
+                for _ in 0..self.read_len(n)? {
+                    self.skip(depth + 1)?;
+                    self.skip(depth + 1)?;
+                }
+            }
+            MAJOR_TAG => self.skip(depth + 1)?,
+            MAJOR_SIMPLE if [SIMPLE_FALSE, SIMPLE_TRUE, SIMPLE_NULL].contains(&n) => (),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn read(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let (major, n) = self.read_head()?;
+        let value = match major {
+            MAJOR_UNSIGNED if n <= i64::MAX as u64 => Value::Integer(n as i64),
+            MAJOR_NEGATIVE if n <= i64::MAX as u64 => Value::Integer(!(n as i64)),
+            MAJOR_BYTES => {
+                let len = self.read_len(n)?;
+                Value::Bytes(self.take(len)?.to_vec())
+            }
+            MAJOR_TEXT => {
+                let len = self.read_len(n)?;
+                Value::Text(String::from_utf8(self.take(len)?.to_vec()).ok()?)
+            }
+            MAJOR_ARRAY => {
+                let len = self.read_len(n)?;
+                let items = (0..len).map(|_| self.read(depth + 1));
+                Value::Array(items.collect::<Option<_>>()?)
+            }
+            MAJOR_MAP => {
+                let len = self.read_len(n)?;
+                let mut entries: Vec<(Value, Value)> = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let key = self.read(depth + 1)?;
+                    if entries.iter().any(|(k, _)| *k == key) {
+                        return None;
+                    }
+                    let value = self.read(depth + 1)?;
+                    entries.push((key, value));
+                }
+                Value::Map(entries)
+            }
+            MAJOR_TAG => Value::Tag(n, Box::new(self.read(depth + 1)?)),
+            MAJOR_SIMPLE => match n {
+                SIMPLE_FALSE => Value::Bool(false),
+                SIMPLE_TRUE => Value::Bool(true),
+                SIMPLE_NULL => Value::Null,
+                // floats and other simple values
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(value: &Value) -> String {
+        value
+            .encode()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    #[test]
+    fn rfc8949_examples() {
+        assert_eq!(hex(&Value::Integer(0)), "00");
+        assert_eq!(hex(&Value::Integer(23)), "17");
+        assert_eq!(hex(&Value::Integer(24)), "1818");
+        assert_eq!(hex(&Value::Integer(1000)), "1903e8");
+        assert_eq!(hex(&Value::Integer(1000000)), "1a000f4240");
+        assert_eq!(hex(&Value::Integer(1000000000000)), "1b000000e8d4a51000");
+        assert_eq!(hex(&Value::Integer(-1)), "20");
+        assert_eq!(hex(&Value::Integer(-1000)), "3903e7");
+        assert_eq!(hex(&Value::Integer(i64::MIN)), "3b7fffffffffffffff");
+        assert_eq!(hex(&Value::Bytes(vec![1, 2, 3, 4])), "4401020304");
+        assert_eq!(hex(&Value::Text("IETF".to_owned())), "6449455446");
+        assert_eq!(hex(&Value::Bool(true)), "f5");
+        assert_eq!(hex(&Value::Null), "f6");
+        let nested = Value::Array(vec![
+            Value::Integer(1),
+            Value::Array(vec![Value::Integer(2), Value::Integer(3)]),
+        ]);
+        assert_eq!(hex(&nested), "8201820203");
+        let map = Value::Map(vec![
+            (Value::Text("a".to_owned()), Value::Integer(1)),
+            (Value::Text("b".to_owned()), Value::Array(vec![])),
+        ]);
+        assert_eq!(hex(&map), "a2616101616280");
+        let tagged = Value::Tag(1, Box::new(Value::Integer(1363896240)));
+        assert_eq!(hex(&tagged), "c11a514b67b0");
+
+        for value in [nested, map, tagged, Value::Integer(i64::MIN)].iter() {
+            assert_eq!(Value::decode(&value.encode()).as_ref(), Some(value));
+        }
+    }
+
+    #[test]
+    fn decode_should_reject_unsupported() {
+        // indefinite length array
+        assert_eq!(Value::decode(&[0x9f, 0x01, 0xff]), None);
+        // half precision float
+        assert_eq!(Value::decode(&[0xf9, 0x3c, 0x00]), None);
+        // out of i64 range
+        assert_eq!(Value::decode(&[0x1b, 0xff, 0, 0, 0, 0, 0, 0, 0]), None);
+        // duplicated map key
+        assert_eq!(Value::decode(&[0xa2, 0x01, 0x01, 0x01, 0x02]), None);
+        // truncated, trailing data and huge length
+        assert_eq!(Value::decode(&[0x44, 0x01]), None);
+        assert_eq!(Value::decode(&[0x01, 0x01]), None);
+        assert_eq!(
+            Value::decode(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            None
+        );
+        // invalid utf-8
+        assert_eq!(Value::decode(&[0x61, 0xff]), None);
+        // too deep
+        assert_eq!(Value::decode(&[0x81; 32]), None);
+    }
+}
@@ -0,0 +1,463 @@
+//! COSE_Sign1 (RFC 9052) envelope and CWT claims (RFC 8392), a binary alternative to
+//! `SignedMessage` for constrained clients
+//!
+//! The protected header holds the algorithm and key id, so both are covered by the signature.
+//! Messages are signed by Ed25519 (`EdDSA`) or ECDSA P-256 (`ES256`) keys.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+pub use cbor::Value;
+
+use cbor::{Reader, MAJOR_ARRAY, MAJOR_BYTES, MAJOR_TAG};
+
+use crate::crypto::{Algorithm, PublicKey, SecretKey, Signer};
+use crate::error::Error::{self, *};
+use crate::message::Envelope;
+
+pub const COSE_SIGN1_TAG: u64 = 18;
+pub const CWT_TAG: u64 = 61;
+
+const HEADER_ALG: i64 = 1;
+const HEADER_CRIT: i64 = 2;
+const HEADER_KID: i64 = 4;
+const ALG_EDDSA: i64 = -8;
+const ALG_ES256: i64 = -7;
+const SIGNATURE1_CONTEXT: &str = "Signature1";
+
+const CLAIM_ISS: i64 = 1;
+const CLAIM_SUB: i64 = 2;
+const CLAIM_AUD: i64 = 3;
+const CLAIM_EXP: i64 = 4;
+const CLAIM_NBF: i64 = 5;
+const CLAIM_IAT: i64 = 6;
+const CLAIM_CTI: i64 = 7;
+
+fn cose_algorithm(algorithm: Algorithm) -> i64 {
+    match algorithm {
+        Algorithm::Ed25519 => ALG_EDDSA,
+        Algorithm::EcdsaP256Sha256 => ALG_ES256,
+    }
+}
+
+/// COSE_Sign1 message
+pub struct CoseSign1 {
+    /// Serialized protected header, exactly as signed
+    protected: Vec<u8>,
+    alg: i64,
+    key_id: Option<String>,
+    payload: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl CoseSign1 {
+    /// Sign `payload`, e.g. encoded `CwtClaims`, and embed `key_id` in the protected header
+    pub fn create<S: Signer + ?Sized>(
+        payload: Vec<u8>,
+        signer: &S,
+        key_id: Option<String>,
+    ) -> Result<Self, Error> {
+        Self::create_with_associated_data(payload, signer, key_id, &[])
+    }
+
+    /// Sign `payload` with `associated_data` as the external AAD of the `Sig_structure`
+    pub fn create_with_associated_data<S: Signer + ?Sized>(
+        payload: Vec<u8>,
+        signer: &S,
+        key_id: Option<String>,
+        associated_data: &[u8],
+    ) -> Result<Self, Error> {
+        let alg = [Algorithm::Ed25519, Algorithm::EcdsaP256Sha256]
+            .iter()
+            .find(|algorithm| algorithm.name() == signer.algorithm_name())
+            .map(|algorithm| cose_algorithm(*algorithm))
+            .ok_or(UnsupportedKeyAlgorithm)?;
+        let mut header = vec![(Value::Integer(HEADER_ALG), Value::Integer(alg))];
+        if let Some(key_id) = &key_id {
+            let kid = Value::Bytes(key_id.as_bytes().to_vec());
+            header.push((Value::Integer(HEADER_KID), kid));
+        }
+        let protected = Value::Map(header).encode();
+        let signature = signer.sign(&signing_input(&protected, associated_data, &payload))?;
+        Ok(Self {
+            protected,
+            alg,
+            key_id,
+            payload,
+            signature,
+        })
+    }
+
+    /// Encode as tagged COSE_Sign1
+    pub fn encode(&self) -> Vec<u8> {
+        let message = Value::Array(vec![
+            Value::Bytes(self.protected.clone()),
+            Value::Map(vec![]),
+            Value::Bytes(self.payload.clone()),
+            Value::Bytes(self.signature.clone()),
+        ]);
+        Value::Tag(COSE_SIGN1_TAG, Box::new(message)).encode()
+    }
+
+    /// Decode COSE_Sign1, either untagged or tagged and optionally wrapped in the CWT tag
+    ///
+    /// Messages without algorithm in the protected header, with detached payload or with critical
+    /// header parameters are rejected.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut value = Value::decode(bytes)?;
+        if let Value::Tag(CWT_TAG, inner) = value {
+            value = *inner;
+        }
+        if let Value::Tag(COSE_SIGN1_TAG, inner) = value {
+            value = *inner;
+        }
+        let items = match value {
+            Value::Array(items) => items,
+            _ => return None,
+        };
+        let (protected, unprotected, payload, signature) = match items.as_slice() {
+            [Value::Bytes(protected), unprotected @ Value::Map(_), Value::Bytes(payload), Value::Bytes(signature)] => {
+                (protected, unprotected, payload, signature)
+            }
+            _ => return None,
+        };
+        let header = Value::decode(protected)?;
+        if header.get(HEADER_CRIT).is_some() || unprotected.get(HEADER_CRIT).is_some() {
+            return None;
+        }
+        let alg = header.get(HEADER_ALG)?.as_integer()?;
+        // the same parameter must not be in both headers
+        let key_id = match (header.get(HEADER_KID), unprotected.get(HEADER_KID)) {
+            (Some(kid), None) | (None, Some(kid)) => {
+                Some(String::from_utf8(kid.as_bytes()?.to_vec()).ok()?)
+            }
+            (None, None) => None,
+            (Some(_), Some(_)) => return None,
+        };
+        Some(Self {
+            protected: protected.clone(),
+            alg,
+            key_id,
+            payload: payload.clone(),
+            signature: signature.clone(),
+        })
+    }
+
+    /// Length of the payload of an encoded COSE_Sign1, read without decoding the message so it's
+    /// bounded first, `None` if `bytes` don't start like a COSE_Sign1
+    pub fn payload_len(bytes: &[u8]) -> Option<usize> {
+        let mut reader = Reader::new(bytes);
+        let mut head = reader.read_head()?;
+        for tag in &[CWT_TAG, COSE_SIGN1_TAG] {
+            if head == (MAJOR_TAG, *tag) {
+                head = reader.read_head()?;
+            }
+        }
+        if head != (MAJOR_ARRAY, 4) {
+            return None;
+        }
+        // protected and unprotected header
+        reader.skip(0)?;
+        reader.skip(0)?;
+        match reader.read_head()? {
+            (MAJOR_BYTES, len) => usize::try_from(len).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn verify(&self, key: &PublicKey) -> bool {
+        self.verify_with_associated_data(key, &[])
+    }
+
+    /// Verify message signed with `associated_data` as external AAD
+    pub fn verify_with_associated_data(&self, key: &PublicKey, associated_data: &[u8]) -> bool {
+        self.alg == cose_algorithm(key.algorithm())
+            && key.verify(
+                &signing_input(&self.protected, associated_data, &self.payload),
+                &self.signature,
+            )
+    }
+
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+/// `Sig_structure` of COSE_Sign1
+fn signing_input(protected: &[u8], external_aad: &[u8], payload: &[u8]) -> Vec<u8> {
+    Value::Array(vec![
+        Value::Text(SIGNATURE1_CONTEXT.to_owned()),
+        Value::Bytes(protected.to_vec()),
+        Value::Bytes(external_aad.to_vec()),
+        Value::Bytes(payload.to_vec()),
+    ])
+    .encode()
+}
+
+impl Envelope for CoseSign1 {
+    fn key_id(&self) -> Option<Cow<'_, str>> {
+        self.key_id().map(Cow::Borrowed)
+    }
+
+    fn verify(&self, key: &PublicKey, associated_data: &[u8]) -> bool {
+        self.verify_with_associated_data(key, associated_data)
+    }
+
+    /// MACed messages are COSE_Mac0, which isn't supported
+    fn verify_hmac(&self, _key: &SecretKey, _associated_data: &[u8]) -> bool {
+        false
+    }
+
+    fn message(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+/// CWT claims set, times are seconds since the Unix epoch
+///
+/// Private claims use negative or unregistered labels, e.g. the policies of an access token.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CwtClaims {
+    pub iss: Option<String>,
+    pub sub: Option<String>,
+    pub aud: Option<String>,
+    pub exp: Option<i64>,
+    pub nbf: Option<i64>,
+    pub iat: Option<i64>,
+    pub cti: Option<Vec<u8>>,
+    pub private: BTreeMap<i64, Value>,
+}
+
+impl CwtClaims {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let text = |label, value: &Option<String>| {
+            value
+                .as_ref()
+                .map(|value| (Value::Integer(label), Value::Text(value.clone())))
+        };
+        let integer = |label, value: Option<i64>| {
+            value.map(|value| (Value::Integer(label), Value::Integer(value)))
+        };
+        let registered = vec![
+            text(CLAIM_ISS, &self.iss),
+            text(CLAIM_SUB, &self.sub),
+            text(CLAIM_AUD, &self.aud),
+            integer(CLAIM_EXP, self.exp),
+            integer(CLAIM_NBF, self.nbf),
+            integer(CLAIM_IAT, self.iat),
+            self.cti
+                .as_ref()
+                .map(|cti| (Value::Integer(CLAIM_CTI), Value::Bytes(cti.clone()))),
+        ];
+        let private = self
+            .private
+            .iter()
+            .map(|(label, value)| (Value::Integer(*label), value.clone()));
+        Value::Map(registered.into_iter().flatten().chain(private).collect()).encode()
+    }
+
+    /// Decode claims, registered claims of a wrong type are rejected
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let entries = match Value::decode(bytes)? {
+            Value::Map(entries) => entries,
+            _ => return None,
+        };
+        let mut claims = Self::default();
+        for (label, value) in entries {
+            let label = label.as_integer()?;
+            let text = || value.as_text().map(str::to_owned);
+            match label {
+                CLAIM_ISS => claims.iss = Some(text()?),
+                CLAIM_SUB => claims.sub = Some(text()?),
+                CLAIM_AUD => claims.aud = Some(text()?),
+                CLAIM_EXP => claims.exp = Some(value.as_integer()?),
+                CLAIM_NBF => claims.nbf = Some(value.as_integer()?),
+                CLAIM_IAT => claims.iat = Some(value.as_integer()?),
+                CLAIM_CTI => claims.cti = Some(value.as_bytes()?.to_vec()),
+                _ => {
+                    claims.private.insert(label, value);
+                }
+            }
+        }
+        Some(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::tests::{ecdsa_test_keys, get_test_private_key, get_test_public_key, hex};
+    use crate::crypto::PrivateKey;
+
+    use super::*;
+
+    // RFC 8392 appendix A.1
+    const CLAIMS: &str = "a70175636f61703a2f2f61732e6578616d706c652e636f6d02656572696b77037818636f61703a2f2f6c696768742e6578616d706c652e636f6d041a5612aeb0051a5610d9f0061a5610d9f007420b71";
+    // RFC 8392 appendix A.3, signed by the P-256 key of appendix A.2.3
+    const SIGNED_CWT: &str = "d28443a10126a104524173796d6d657472696345434453413235365850a70175636f61703a2f2f61732e6578616d706c652e636f6d02656572696b77037818636f61703a2f2f6c696768742e6578616d706c652e636f6d041a5612aeb0051a5610d9f0061a5610d9f007420b7158405427c1ff28d23fbad1f29c4c7c6a555e601d6fa29f9179bc3d7438bacaca5acd08c8d4d4f96131680c429a01f85951ecee743a52b9b63632c57209120e1c9e30";
+    const P256_X: &str = "143329cce7868e416927599cf65a34f3ce2ffda55a7eca69ed8919a394d42f0f";
+    const P256_Y: &str = "60f7f1a780d8a783bfb7a2dd6b2796e8128dbbcef9d3d168db9529971a36e7b9";
+
+    fn rfc8392_claims() -> CwtClaims {
+        CwtClaims {
+            iss: Some("coap://as.example.com".to_owned()),
+            sub: Some("erikw".to_owned()),
+            aud: Some("coap://light.example.com".to_owned()),
+            exp: Some(1444064944),
+            nbf: Some(1443944944),
+            iat: Some(1443944944),
+            cti: Some(vec![0x0b, 0x71]),
+            private: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn cwt_claims() {
+        assert_eq!(rfc8392_claims().to_bytes(), hex(CLAIMS));
+        assert_eq!(CwtClaims::from_bytes(&hex(CLAIMS)), Some(rfc8392_claims()));
+
+        let mut claims = rfc8392_claims();
+        claims.private.insert(-65537, Value::Bytes(vec![1, 2]));
+        assert_eq!(CwtClaims::from_bytes(&claims.to_bytes()), Some(claims));
+
+        // exp must be integer
+        let bad = Value::Map(vec![(
+            Value::Integer(CLAIM_EXP),
+            Value::Text("tomorrow".to_owned()),
+        )]);
+        assert_eq!(CwtClaims::from_bytes(&bad.encode()), None);
+    }
+
+    #[test]
+    fn should_verify_rfc8392_example() {
+        let public_key = PublicKey::new(
+            Algorithm::EcdsaP256Sha256,
+            &[&[0x04][..], &hex(P256_X), &hex(P256_Y)].concat(),
+        )
+        .unwrap();
+        let message = CoseSign1::decode(&hex(SIGNED_CWT)).unwrap();
+        assert_eq!(message.key_id(), Some("AsymmetricECDSA256"));
+        assert_eq!(message.payload(), hex(CLAIMS).as_slice());
+        assert!(message.verify(&public_key));
+
+        let mut tampered = hex(SIGNED_CWT);
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(!CoseSign1::decode(&tampered).unwrap().verify(&public_key));
+    }
+
+    #[test]
+    fn sign_verify() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let payload = rfc8392_claims().to_bytes();
+        let message = CoseSign1::create(payload.clone(), &private_key, Some("k1".to_owned()))
+            .unwrap()
+            .encode();
+        assert_eq!(message[0], 0xd2);
+
+        let decoded = CoseSign1::decode(&message).unwrap();
+        assert_eq!(decoded.key_id(), Some("k1"));
+        assert_eq!(decoded.payload(), payload.as_slice());
+        assert!(decoded.verify(&public_key));
+
+        // key of other algorithm
+        let (ecdsa_private_key, ecdsa_public_key) = ecdsa_test_keys();
+        assert!(!decoded.verify(&ecdsa_public_key));
+        let message = CoseSign1::create(payload, &ecdsa_private_key, None).unwrap();
+        assert!(message.verify(&ecdsa_public_key));
+        assert!(!message.verify(&public_key));
+    }
+
+    #[test]
+    fn external_aad() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let message =
+            CoseSign1::create_with_associated_data(b"payload".to_vec(), &private_key, None, b"aad")
+                .unwrap();
+        let decoded = CoseSign1::decode(&message.encode()).unwrap();
+        assert!(decoded.verify_with_associated_data(&public_key, b"aad"));
+        assert!(!decoded.verify_with_associated_data(&public_key, b"other"));
+        assert!(!decoded.verify(&public_key));
+    }
+
+    #[test]
+    fn decode_should_reject_malformed() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let message = CoseSign1::create(b"payload".to_vec(), &private_key, None).unwrap();
+        let encode = |protected: Value, unprotected: Value| {
+            Value::Array(vec![
+                Value::Bytes(protected.encode()),
+                unprotected,
+                Value::Bytes(message.payload.clone()),
+                Value::Bytes(message.signature.clone()),
+            ])
+            .encode()
+        };
+        let alg = (Value::Integer(HEADER_ALG), Value::Integer(ALG_EDDSA));
+        let kid = (Value::Integer(HEADER_KID), Value::Bytes(b"k1".to_vec()));
+        let crit = (
+            Value::Integer(HEADER_CRIT),
+            Value::Array(vec![Value::Integer(-70000)]),
+        );
+
+        // untagged
+        let valid = encode(Value::Map(vec![alg.clone()]), Value::Map(vec![]));
+        assert!(CoseSign1::decode(&valid).is_some());
+        // algorithm is only trusted from protected header
+        let unprotected_alg = encode(Value::Map(vec![]), Value::Map(vec![alg.clone()]));
+        assert!(CoseSign1::decode(&unprotected_alg).is_none());
+        let kid_in_both = encode(
+            Value::Map(vec![alg.clone(), kid.clone()]),
+            Value::Map(vec![kid]),
+        );
+        assert!(CoseSign1::decode(&kid_in_both).is_none());
+        let critical = encode(Value::Map(vec![alg, crit]), Value::Map(vec![]));
+        assert!(CoseSign1::decode(&critical).is_none());
+        // detached payload
+        let detached = Value::Array(vec![
+            Value::Bytes(message.protected.clone()),
+            Value::Map(vec![]),
+            Value::Null,
+            Value::Bytes(message.signature.clone()),
+        ]);
+        assert!(CoseSign1::decode(&detached.encode()).is_none());
+        assert!(CoseSign1::decode(b"not cbor").is_none());
+    }
+
+    #[test]
+    fn payload_len() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let message = CoseSign1::create(b"payload".to_vec(), &private_key, None).unwrap();
+        let tagged = message.encode();
+        assert_eq!(CoseSign1::payload_len(&tagged), Some(7));
+        let cwt = Value::Tag(CWT_TAG, Box::new(Value::decode(&tagged).unwrap())).encode();
+        assert_eq!(CoseSign1::payload_len(&cwt), Some(7));
+        let unprotected = Value::Map(vec![(
+            Value::Integer(HEADER_KID),
+            Value::Bytes(b"k1".to_vec()),
+        )]);
+        let untagged = Value::Array(vec![
+            Value::Bytes(message.protected.clone()),
+            unprotected,
+            Value::Bytes(vec![0; 300]),
+            Value::Bytes(message.signature.clone()),
+        ]);
+        assert_eq!(CoseSign1::payload_len(&untagged.encode()), Some(300));
+
+        // read from the head only, the truncated payload is left to `decode`
+        assert_eq!(
+            CoseSign1::payload_len(&tagged[..tagged.len() - 70]),
+            Some(7)
+        );
+        assert_eq!(CoseSign1::payload_len(b"not cbor"), None);
+        assert_eq!(CoseSign1::payload_len(b"eyJ2IjoxfQ.e30.c2ln"), None);
+    }
+}
+
+mod cbor;
@@ -0,0 +1,101 @@
+//! Minimal DER reader and writer covering the structures of PKCS#8 and SubjectPublicKeyInfo
+
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_SEQUENCE: u8 = 0x30;
+
+pub struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self(input)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Read next element and return its tag and contents
+    pub fn read_any(&mut self) -> Option<(u8, &'a [u8])> {
+        let (&tag, rest) = self.0.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+        let (len, rest) = match first {
+            0x00..=0x7f => (first as usize, rest),
+            0x81 => {
+                let (&len, rest) = rest.split_first()?;
+                (len as usize, rest)
+            }
+            0x82 if rest.len() >= 2 => ((rest[0] as usize) << 8 | rest[1] as usize, &rest[2..]),
+            // indefinite or unreasonably large lengths never appear in key structures
+            _ => return None,
+        };
+        if rest.len() < len {
+            return None;
+        }
+        let (contents, rest) = rest.split_at(len);
+        self.0 = rest;
+        Some((tag, contents))
+    }
+
+    /// Read next element which must have `tag` and return its contents
+    pub fn read(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.read_any()? {
+            (t, contents) if t == tag => Some(contents),
+            _ => None,
+        }
+    }
+}
+
+/// Append element with `tag` and `contents` to `out`
+pub fn write(tag: u8, contents: &[u8], out: &mut Vec<u8>) {
+    let len = contents.len();
+    out.push(tag);
+    if len < 0x80 {
+        out.push(len as u8);
+    } else if len <= 0xff {
+        out.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        assert!(len <= 0xffff, "DER element is too large");
+        out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+    }
+    out.extend_from_slice(contents);
+}
+
+/// Encode element with `tag` and `contents`
+pub fn element(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(contents.len() + 4);
+    write(tag, contents, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_write() {
+        let short = vec![1u8; 3];
+        let long = vec![2u8; 200];
+        let longer = vec![3u8; 300];
+        let mut contents = element(TAG_OCTET_STRING, &short);
+        write(TAG_OCTET_STRING, &long, &mut contents);
+        write(TAG_BIT_STRING, &longer, &mut contents);
+        let encoded = element(TAG_SEQUENCE, &contents);
+
+        let mut reader = Reader::new(&encoded);
+        let mut seq = Reader::new(reader.read(TAG_SEQUENCE).unwrap());
+        assert!(reader.is_empty());
+        assert_eq!(seq.read(TAG_OCTET_STRING), Some(short.as_slice()));
+        assert_eq!(seq.read(TAG_OCTET_STRING), Some(long.as_slice()));
+        assert_eq!(seq.read(TAG_OCTET_STRING), None);
+    }
+
+    #[test]
+    fn read_truncated() {
+        assert_eq!(Reader::new(&[TAG_OCTET_STRING, 3, 0, 0]).read_any(), None);
+        assert_eq!(Reader::new(&[TAG_OCTET_STRING, 0x80]).read_any(), None);
+        assert_eq!(Reader::new(&[TAG_OCTET_STRING]).read_any(), None);
+    }
+}
@@ -0,0 +1,225 @@
+//! Encryption of messages to a recipient's X25519 key
+//!
+//! Every message gets a fresh ephemeral X25519 key. Its shared secret with the recipient key is
+//! expanded by HKDF-SHA256, salted by both public keys, into a ChaCha20-Poly1305 key which is used
+//! exactly once. The ciphertext is `ephemeral public key || sealed message || tag`.
+
+use std::convert::TryInto;
+use std::fmt;
+
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+use ring::digest;
+use ring::hkdf;
+use ring::rand::{SecureRandom, SystemRandom};
+use x25519_dalek::{SharedSecret, StaticSecret};
+use zeroize::Zeroizing;
+
+use crate::crypto::{fingerprint, RNG_ERROR, SECRET_LEN};
+use crate::error::Error::{self, *};
+
+const X25519_KEY_LEN: usize = 32;
+/// HKDF info of the content encryption key, changing it breaks every encrypted message
+const ENCRYPTION_INFO: &[u8] = b"tokidator x25519 chacha20poly1305";
+
+/// X25519 public key of the service which is allowed to read encrypted messages
+#[derive(Clone)]
+pub struct EncryptionKey(x25519_dalek::PublicKey);
+
+impl EncryptionKey {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; X25519_KEY_LEN] = bytes.try_into().ok()?;
+        Some(Self(bytes.into()))
+    }
+
+    pub fn from_base64<T: ?Sized + AsRef<[u8]>>(input: &T) -> Option<Self> {
+        base64::decode_config(input, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|bytes| Self::from_bytes(&bytes))
+    }
+
+    pub fn to_base64(&self) -> String {
+        base64::encode_config(self.as_bytes(), base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    /// Base64url encoded SHA-256 of the key, which names the recipient of a signed message
+    pub fn thumbprint(&self) -> String {
+        base64::encode_config(
+            digest::digest(&digest::SHA256, self.as_bytes()),
+            base64::URL_SAFE_NO_PAD,
+        )
+    }
+
+    /// Encrypt `plaintext` so only the holder of the matching `DecryptionKey` can read it
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut seed = Zeroizing::new([0u8; SECRET_LEN]);
+        SystemRandom::new()
+            .fill(&mut *seed)
+            .map_err(|_| EncryptionFail)?;
+        let ephemeral = StaticSecret::from(*seed);
+        let ephemeral_public = x25519_dalek::PublicKey::from(&ephemeral);
+        let key = content_key(
+            &ephemeral.diffie_hellman(&self.0),
+            &ephemeral_public,
+            &self.0,
+        )
+        .ok_or(EncryptionFail)?;
+
+        let mut sealed = plaintext.to_vec();
+        key.seal_in_place_append_tag(single_use_nonce(), Aad::empty(), &mut sealed)
+            .map_err(|_| EncryptionFail)?;
+        Ok([ephemeral_public.as_bytes(), sealed.as_slice()].concat())
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("fingerprint", &fingerprint(self.as_bytes()))
+            .finish()
+    }
+}
+
+/// X25519 private key of the service reading encrypted messages, wiped on drop
+#[derive(Clone)]
+pub struct DecryptionKey(StaticSecret);
+
+impl DecryptionKey {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: Zeroizing<[u8; X25519_KEY_LEN]> = Zeroizing::new(bytes.try_into().ok()?);
+        Some(Self(StaticSecret::from(*bytes)))
+    }
+
+    pub fn from_base64<T: ?Sized + AsRef<[u8]>>(input: &T) -> Option<Self> {
+        base64::decode_config(input, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .map(Zeroizing::new)
+            .and_then(|bytes| Self::from_bytes(&bytes))
+    }
+
+    pub fn to_base64(&self) -> Zeroizing<String> {
+        Zeroizing::new(base64::encode_config(
+            self.0.as_bytes(),
+            base64::URL_SAFE_NO_PAD,
+        ))
+    }
+
+    /// Generate key from the operating system's secure random generator
+    ///
+    /// # Panics
+    /// If the operating system fails to provide random bytes
+    pub fn generate() -> Self {
+        let mut seed = Zeroizing::new([0u8; X25519_KEY_LEN]);
+        SystemRandom::new().fill(&mut *seed).expect(RNG_ERROR);
+        Self(StaticSecret::from(*seed))
+    }
+
+    /// Key handed to issuers so they can encrypt messages to this key
+    pub fn public_key(&self) -> EncryptionKey {
+        EncryptionKey((&self.0).into())
+    }
+
+    /// Decrypt message of `EncryptionKey::encrypt`, fails if it was encrypted to another key or
+    /// modified
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        if ciphertext.len() < X25519_KEY_LEN + CHACHA20_POLY1305.tag_len() {
+            return Err(DecryptionFail);
+        }
+        let (ephemeral_public, sealed) = ciphertext.split_at(X25519_KEY_LEN);
+        let ephemeral_public: [u8; X25519_KEY_LEN] = ephemeral_public.try_into().unwrap();
+        let ephemeral_public = x25519_dalek::PublicKey::from(ephemeral_public);
+        let key = content_key(
+            &self.0.diffie_hellman(&ephemeral_public),
+            &ephemeral_public,
+            &self.public_key().0,
+        )
+        .ok_or(DecryptionFail)?;
+
+        let mut buffer = sealed.to_vec();
+        let len = key
+            .open_in_place(single_use_nonce(), Aad::empty(), &mut buffer)
+            .map_err(|_| DecryptionFail)?
+            .len();
+        buffer.truncate(len);
+        Ok(buffer)
+    }
+}
+
+impl fmt::Debug for DecryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecryptionKey")
+            .field("public_key", &fingerprint(self.public_key().as_bytes()))
+            .finish()
+    }
+}
+
+/// Derive content encryption key, `None` if a low order point forced a predictable secret
+fn content_key(
+    shared_secret: &SharedSecret,
+    ephemeral_public: &x25519_dalek::PublicKey,
+    recipient: &x25519_dalek::PublicKey,
+) -> Option<LessSafeKey> {
+    if !shared_secret.was_contributory() {
+        return None;
+    }
+    let salt = [&ephemeral_public.as_bytes()[..], &recipient.as_bytes()[..]].concat();
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &salt).extract(shared_secret.as_bytes());
+    let info = [ENCRYPTION_INFO];
+    let okm = prk.expand(&info, &CHACHA20_POLY1305).ok()?;
+    Some(LessSafeKey::new(UnboundKey::from(okm)))
+}
+
+/// Every content key encrypts a single message, so a constant nonce is never reused
+fn single_use_nonce() -> Nonce {
+    Nonce::assume_unique_for_key([0u8; aead::NONCE_LEN])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt() {
+        let key = DecryptionKey::generate();
+        let ciphertext = key.public_key().encrypt(b"message").unwrap();
+        assert_eq!(key.decrypt(&ciphertext).unwrap(), b"message");
+
+        // fresh ephemeral key for every message
+        assert_ne!(key.public_key().encrypt(b"message").unwrap(), ciphertext);
+        let other = DecryptionKey::generate();
+        assert!(other.decrypt(&ciphertext).is_err());
+    }
+
+    #[test]
+    fn decrypt_should_reject_modified() {
+        let key = DecryptionKey::generate();
+        let ciphertext = key.public_key().encrypt(b"message").unwrap();
+        for i in 0..ciphertext.len() {
+            let mut modified = ciphertext.clone();
+            modified[i] ^= 1;
+            assert!(key.decrypt(&modified).is_err());
+        }
+        assert!(key.decrypt(&ciphertext[..ciphertext.len() - 1]).is_err());
+        assert!(key.decrypt(&[]).is_err());
+    }
+
+    #[test]
+    fn encrypt_should_reject_low_order_key() {
+        let key = EncryptionKey::from_bytes(&[0u8; X25519_KEY_LEN]).unwrap();
+        assert!(key.encrypt(b"message").is_err());
+    }
+
+    #[test]
+    fn key_encoding() {
+        let key = DecryptionKey::generate();
+        let decoded = DecryptionKey::from_base64(&*key.to_base64()).unwrap();
+        assert_eq!(decoded.public_key().as_bytes(), key.public_key().as_bytes());
+        let public_key = EncryptionKey::from_base64(&key.public_key().to_base64()).unwrap();
+        assert_eq!(public_key.as_bytes(), key.public_key().as_bytes());
+        assert!(DecryptionKey::from_bytes(&[1u8; 31]).is_none());
+        assert!(!format!("{:?}", key).contains(key.to_base64().as_str()));
+    }
+}
@@ -0,0 +1,409 @@
+//! JSON Web Key (RFC 7517) representation of keys
+//!
+//! Ed25519 keys use the `OKP` key type defined by RFC 8037, ECDSA P-256 keys the `EC` key type
+//! defined by RFC 7518.
+
+use std::fmt;
+
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto::{
+    Algorithm, KeySet, PrivateKey, PublicKey, ED25519_PUBLIC_KEY_LEN, SECRET_LEN,
+    UNCOMPRESSED_POINT_TAG,
+};
+use crate::encoding::{b64dec, b64enc};
+use crate::error::Error::{self, *};
+
+const KTY_OKP: &str = "OKP";
+const KTY_EC: &str = "EC";
+const CRV_ED25519: &str = "Ed25519";
+const CRV_P256: &str = "P-256";
+const USE_SIGNATURE: &str = "sig";
+const P256_COORDINATE_LEN: usize = 32;
+
+/// JSON Web Key, private part `d` is wiped on drop and never shown by `Debug`
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub d: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, rename = "use", skip_serializing_if = "Option::is_none")]
+    pub key_use: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+}
+
+/// JSON Web Key Set document
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+impl Drop for Jwk {
+    fn drop(&mut self) {
+        self.d.zeroize();
+    }
+}
+
+impl fmt::Debug for Jwk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Jwk")
+            .field("kty", &self.kty)
+            .field("crv", &self.crv)
+            .field("x", &self.x)
+            .field("y", &self.y)
+            .field("d", &self.d.as_ref().map(|_| "<redacted>"))
+            .field("kid", &self.kid)
+            .field("key_use", &self.key_use)
+            .field("alg", &self.alg)
+            .finish()
+    }
+}
+
+impl Jwk {
+    /// `None` if `public_key` isn't a well formed key of `algorithm`
+    fn new(
+        algorithm: Algorithm,
+        kid: Option<&str>,
+        public_key: &[u8],
+        d: Option<&[u8]>,
+    ) -> Option<Self> {
+        let (kty, crv, x, y) = match (algorithm, public_key.split_first()) {
+            (Algorithm::Ed25519, _) if public_key.len() == ED25519_PUBLIC_KEY_LEN => {
+                (KTY_OKP, CRV_ED25519, public_key, None)
+            }
+            (Algorithm::EcdsaP256Sha256, Some((&UNCOMPRESSED_POINT_TAG, point)))
+                if point.len() == 2 * P256_COORDINATE_LEN =>
+            {
+                let (x, y) = point.split_at(P256_COORDINATE_LEN);
+                (KTY_EC, CRV_P256, x, Some(y))
+            }
+            _ => return None,
+        };
+        Some(Self {
+            kty: kty.to_owned(),
+            crv: Some(crv.to_owned()),
+            x: Some(b64enc(x)),
+            y: y.map(b64enc),
+            d: d.map(b64enc),
+            kid: kid.map(str::to_owned),
+            key_use: Some(USE_SIGNATURE.to_owned()),
+            alg: Some(algorithm.name().to_owned()),
+        })
+    }
+
+    /// Check key type against its declared curve, algorithm and usage
+    fn algorithm(&self) -> Result<Algorithm, Error> {
+        let algorithm = match (self.kty.as_str(), self.crv.as_deref()) {
+            (KTY_OKP, Some(CRV_ED25519)) => Algorithm::Ed25519,
+            (KTY_EC, Some(CRV_P256)) => Algorithm::EcdsaP256Sha256,
+            _ => return Err(UnsupportedKeyAlgorithm),
+        };
+        match self.alg.as_deref() {
+            Some(alg) if alg != algorithm.name() => return Err(UnsupportedKeyAlgorithm),
+            _ => (),
+        }
+        match self.key_use.as_deref() {
+            None | Some(USE_SIGNATURE) => Ok(algorithm),
+            Some(_) => Err(UnsupportedKeyAlgorithm),
+        }
+    }
+
+    /// Decode public key, ECDSA keys are returned as uncompressed point
+    fn public_key_bytes(&self, algorithm: Algorithm) -> Result<Vec<u8>, Error> {
+        let x = self.x.as_deref().and_then(b64dec).ok_or(BadKeyEncoding)?;
+        match algorithm {
+            Algorithm::Ed25519 if x.len() == ED25519_PUBLIC_KEY_LEN => Ok(x),
+            Algorithm::EcdsaP256Sha256 => {
+                let y = self.y.as_deref().and_then(b64dec).ok_or(BadKeyEncoding)?;
+                if x.len() != P256_COORDINATE_LEN || y.len() != P256_COORDINATE_LEN {
+                    return Err(BadKeyEncoding);
+                }
+                Ok([&[UNCOMPRESSED_POINT_TAG], x.as_slice(), y.as_slice()].concat())
+            }
+            _ => Err(BadKeyEncoding),
+        }
+    }
+}
+
+impl PublicKey {
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, Error> {
+        let algorithm = jwk.algorithm()?;
+        let bytes = jwk.public_key_bytes(algorithm)?;
+        Self::new(algorithm, &bytes).ok_or(BadKeyEncoding)
+    }
+
+    pub fn to_jwk(&self, kid: Option<&str>) -> Jwk {
+        Jwk::new(self.algorithm(), kid, self.as_bytes(), None)
+            .expect("Public key is checked on construction")
+    }
+
+    /// JWK thumbprint (RFC 7638), base64url encoded SHA-256 of the required members of the JWK
+    ///
+    /// It's the id of a key registered without an explicit one.
+    pub fn thumbprint(&self) -> String {
+        let jwk = self.to_jwk(None);
+        let member = |value: &Option<String>| value.clone().unwrap_or_default();
+        // members in lexicographic order without whitespace, base64url values need no escaping
+        let mut canonical = format!(
+            r#"{{"crv":"{}","kty":"{}","x":"{}""#,
+            member(&jwk.crv),
+            jwk.kty,
+            member(&jwk.x)
+        );
+        if let Some(y) = &jwk.y {
+            canonical.push_str(&format!(r#","y":"{}""#, y));
+        }
+        canonical.push('}');
+        b64enc(digest::digest(&digest::SHA256, canonical.as_bytes()).as_ref())
+    }
+}
+
+impl PrivateKey {
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, Error> {
+        let algorithm = jwk.algorithm()?;
+        let d = jwk
+            .d
+            .as_deref()
+            .and_then(b64dec)
+            .map(Zeroizing::new)
+            .ok_or(BadKeyEncoding)?;
+        if d.len() != SECRET_LEN {
+            return Err(BadKeyEncoding);
+        }
+        match algorithm {
+            Algorithm::Ed25519 => {
+                let key = Self::from_bytes(&d).ok_or(BadKeyEncoding)?;
+                // public key is optional since it can be derived from the seed
+                match jwk.x {
+                    Some(_) if jwk.public_key_bytes(algorithm)? != key.public_key_bytes() => {
+                        Err(BadKeyEncoding)
+                    }
+                    _ => Ok(key),
+                }
+            }
+            Algorithm::EcdsaP256Sha256 => {
+                let public_key = jwk.public_key_bytes(algorithm)?;
+                Self::from_ecdsa_p256_bytes(&d, &public_key).ok_or(BadKeyEncoding)
+            }
+        }
+    }
+
+    /// Thumbprint of the public key, see `PublicKey::thumbprint`
+    pub fn thumbprint(&self) -> String {
+        self.public_key().thumbprint()
+    }
+
+    /// Export key including its private part `d`
+    pub fn to_jwk(&self, kid: Option<&str>) -> Jwk {
+        Jwk::new(
+            self.algorithm(),
+            kid,
+            self.public_key_bytes(),
+            Some(&self.secret),
+        )
+        .expect("Key pair has a well formed public key")
+    }
+}
+
+impl Jwks {
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|_| BadKeyEncoding)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Fail serialize JWKS")
+    }
+}
+
+impl KeySet {
+    /// Load every signature verification key from `jwks`
+    ///
+    /// Keys of unsupported types are skipped as recommended by RFC 7517, malformed keys are an
+    /// error. A key without `kid` is registered under its thumbprint.
+    pub fn from_jwks(jwks: &Jwks) -> Result<Self, Error> {
+        let mut key_set = Self::new();
+        for jwk in &jwks.keys {
+            let key = match PublicKey::from_jwk(jwk) {
+                Ok(key) => key,
+                Err(UnsupportedKeyAlgorithm) => continue,
+                Err(e) => return Err(e),
+            };
+            let kid = jwk.kid.clone().unwrap_or_else(|| key.thumbprint());
+            if key_set.insert(kid, key).is_some() {
+                return Err(BadKeyEncoding);
+            }
+        }
+        Ok(key_set)
+    }
+
+    pub fn to_jwks(&self) -> Jwks {
+        Jwks {
+            keys: self
+                .iter()
+                .map(|(kid, key)| key.to_jwk(Some(kid)))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::tests::{ecdsa_test_keys, get_test_private_key, get_test_public_key};
+    use crate::crypto::Signer;
+
+    use super::*;
+
+    // RFC 8037 appendix A.1 and A.2
+    const RFC8037_PRIVATE_JWK: &str = r#"{"kty":"OKP","crv":"Ed25519",
+        "d":"nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A",
+        "x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#;
+
+    #[test]
+    fn rfc8037_key() {
+        let jwk: Jwk = serde_json::from_str(RFC8037_PRIVATE_JWK).unwrap();
+        let private_key = PrivateKey::from_jwk(&jwk).unwrap();
+        let public_key = PublicKey::from_jwk(&jwk).unwrap();
+        assert!(public_key.verify(b"message", &private_key.sign(b"message").unwrap()));
+
+        let exported = private_key.to_jwk(None);
+        assert_eq!(exported.d, jwk.d);
+        assert_eq!(exported.x, jwk.x);
+        assert_eq!(public_key.to_jwk(None).d, None);
+    }
+
+    #[test]
+    fn thumbprint() {
+        let jwk: Jwk = serde_json::from_str(RFC8037_PRIVATE_JWK).unwrap();
+        let private_key = PrivateKey::from_jwk(&jwk).unwrap();
+        // RFC 8037 appendix A.3
+        let expected = "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k";
+        assert_eq!(private_key.thumbprint(), expected);
+        assert_eq!(private_key.public_key().thumbprint(), expected);
+
+        let (ecdsa_private_key, ecdsa_public_key) = ecdsa_test_keys();
+        assert_eq!(
+            ecdsa_private_key.thumbprint(),
+            ecdsa_public_key.thumbprint()
+        );
+        assert_ne!(ecdsa_public_key.thumbprint(), expected);
+    }
+
+    #[test]
+    fn jwk_round_trip() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let jwk = private_key.to_jwk(Some("k1"));
+        assert_eq!(jwk.kid.as_deref(), Some("k1"));
+        assert_eq!(jwk.x.as_deref(), Some(get_test_public_key().as_str()));
+        assert_eq!(jwk.d.as_deref(), Some(get_test_private_key().as_str()));
+        assert!(!format!("{:?}", jwk).contains(&get_test_private_key()));
+
+        let json = serde_json::to_string(&jwk).unwrap();
+        let decoded = PrivateKey::from_jwk(&serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(decoded.secret, private_key.secret);
+    }
+
+    #[test]
+    fn ecdsa_p256_jwk() {
+        let (private_key, _) = ecdsa_test_keys();
+        let jwk = private_key.to_jwk(Some("ec"));
+        assert_eq!(jwk.kty, "EC");
+        assert_eq!(jwk.alg.as_deref(), Some("ES256"));
+        assert_eq!(
+            jwk.x.as_deref(),
+            Some("ZN_fCv1PSgbXxdSdkYeT9is-lhVg5BkjRVJ13HCO4Nk")
+        );
+        assert_eq!(
+            jwk.y.as_deref(),
+            Some("P8-kwy3t0V6Ov-CWgxIhqfmhcPvr9XtrQISxKUmh7eI")
+        );
+
+        let decoded = PrivateKey::from_jwk(&jwk).unwrap();
+        let public_key = PublicKey::from_jwk(&jwk).unwrap();
+        assert_eq!(public_key.algorithm(), Algorithm::EcdsaP256Sha256);
+        assert!(public_key.verify(b"message", &decoded.sign(b"message").unwrap()));
+
+        // algorithm must match key type
+        let mut jwk = public_key.to_jwk(None);
+        jwk.alg = Some("EdDSA".to_owned());
+        assert!(matches!(
+            PublicKey::from_jwk(&jwk),
+            Err(UnsupportedKeyAlgorithm)
+        ));
+    }
+
+    #[test]
+    fn reject_bad_jwk() {
+        let mut jwk = PublicKey::dummy().to_jwk(None);
+        jwk.crv = Some("X25519".to_owned());
+        assert!(matches!(
+            PublicKey::from_jwk(&jwk),
+            Err(UnsupportedKeyAlgorithm)
+        ));
+
+        let mut jwk = PublicKey::dummy().to_jwk(None);
+        jwk.x = Some("AAAA".to_owned());
+        assert!(matches!(PublicKey::from_jwk(&jwk), Err(BadKeyEncoding)));
+
+        // private key is missing
+        let jwk = PublicKey::dummy().to_jwk(None);
+        assert!(matches!(PrivateKey::from_jwk(&jwk), Err(BadKeyEncoding)));
+
+        // public key doesn't match private key
+        let mut jwk = PrivateKey::dummy().to_jwk(None);
+        jwk.x = Some(get_test_public_key());
+        assert!(matches!(PrivateKey::from_jwk(&jwk), Err(BadKeyEncoding)));
+    }
+
+    #[test]
+    fn jwks_round_trip() {
+        let key_set: KeySet = vec![
+            (
+                "k1",
+                PublicKey::from_base64(&get_test_public_key()).unwrap(),
+            ),
+            ("k2", PublicKey::dummy()),
+        ]
+        .into_iter()
+        .collect();
+        let json = key_set.to_jwks().to_json();
+        let decoded = KeySet::from_jwks(&Jwks::from_json(&json).unwrap()).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded["k2"].as_bytes(), PublicKey::dummy().as_bytes());
+    }
+
+    #[test]
+    fn jwks_should_skip_unsupported_keys() {
+        let json = r#"{"keys":[
+            {"kty":"RSA","kid":"rsa","n":"0vx7agoebGcQSuuPiLJXZpt","e":"AQAB"},
+            {"kty":"OKP","crv":"Ed25519","kid":"ed","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}
+        ]}"#;
+        let key_set = KeySet::from_jwks(&Jwks::from_json(json).unwrap()).unwrap();
+        assert_eq!(key_set.len(), 1);
+        assert!(key_set.contains_key("ed"));
+
+        let json = r#"{"keys":[{"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}]}"#;
+        let key_set = KeySet::from_jwks(&Jwks::from_json(json).unwrap()).unwrap();
+        assert!(key_set.contains_key("kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"));
+
+        assert!(matches!(Jwks::from_json("{}"), Err(BadKeyEncoding)));
+        let duplicated = r#"{"keys":[
+            {"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"},
+            {"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}
+        ]}"#;
+        assert!(matches!(
+            KeySet::from_jwks(&Jwks::from_json(duplicated).unwrap()),
+            Err(BadKeyEncoding)
+        ));
+    }
+}
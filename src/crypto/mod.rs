@@ -0,0 +1,643 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+
+use ring::digest;
+use ring::hkdf;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{
+    EcdsaKeyPair, Ed25519KeyPair, KeyPair, UnparsedPublicKey, VerificationAlgorithm,
+    ECDSA_P256_SHA256_FIXED, ECDSA_P256_SHA256_FIXED_SIGNING, ED25519,
+};
+use zeroize::{Zeroize, Zeroizing};
+
+pub use encryption::{DecryptionKey, EncryptionKey};
+pub use jwk::{Jwk, Jwks};
+pub use traits::Signer;
+
+use crate::encoding::hex;
+use crate::error::Error::{self, *};
+
+/// Length of Ed25519 seed and P-256 private scalar
+const SECRET_LEN: usize = 32;
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+/// Uncompressed point `0x04 || x || y`
+const P256_PUBLIC_KEY_LEN: usize = 65;
+const UNCOMPRESSED_POINT_TAG: u8 = 0x04;
+/// HMAC-SHA256 keys shorter than the hash output weaken the MAC
+const MIN_SECRET_KEY_LEN: usize = 32;
+/// HKDF info of `PrivateKey::derive`, changing it changes every derived key
+const DERIVE_INFO: &[u8] = b"tokidator ed25519 seed";
+const RNG_ERROR: &str = "Fail to get random bytes from operating system";
+/// JOSE name of HMAC-SHA256, the algorithm of `SecretKey`
+const HMAC_SHA256_NAME: &str = "HS256";
+/// Number of digest bytes shown by `Debug` of keys
+const FINGERPRINT_LEN: usize = 8;
+/// Message tagged to fingerprint a secret key, whose material can't be hashed directly
+const SECRET_FINGERPRINT_INFO: &[u8] = b"tokidator secret key fingerprint";
+
+/// Signature algorithm of a key
+///
+/// A key is bound to a single algorithm, so a signature is always verified with the algorithm of
+/// the trusted key and never with one chosen by the message being verified.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Ed25519,
+    /// ECDSA using P-256 and SHA-256 with fixed length `r || s` signatures
+    EcdsaP256Sha256,
+}
+
+impl Algorithm {
+    /// JOSE name (RFC 7518) of the algorithm
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Ed25519 => "EdDSA",
+            Algorithm::EcdsaP256Sha256 => "ES256",
+        }
+    }
+
+    fn verification_algorithm(self) -> &'static dyn VerificationAlgorithm {
+        match self {
+            Algorithm::Ed25519 => &ED25519,
+            Algorithm::EcdsaP256Sha256 => &ECDSA_P256_SHA256_FIXED,
+        }
+    }
+}
+
+enum SigningKeyPair {
+    Ed25519(Ed25519KeyPair),
+    EcdsaP256(EcdsaKeyPair),
+}
+
+/// Private key used to sign messages
+///
+/// The seed or scalar kept for encoding is wiped when the key is dropped and `Debug` only shows the
+/// thumbprint of the public key. The key pair expanded by ring is outside of our control.
+pub struct PrivateKey {
+    key_pair: SigningKeyPair,
+    /// Ed25519 seed or P-256 private scalar
+    secret: [u8; SECRET_LEN],
+}
+
+impl PrivateKey {
+    /// Create Ed25519 private key from its 32 bytes seed
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(bytes).ok()?;
+        let mut secret = [0u8; SECRET_LEN];
+        secret.copy_from_slice(bytes);
+        Some(Self {
+            key_pair: SigningKeyPair::Ed25519(key_pair),
+            secret,
+        })
+    }
+
+    /// Create ECDSA P-256 private key from its big-endian private scalar and uncompressed public key
+    pub fn from_ecdsa_p256_bytes(private_key: &[u8], public_key: &[u8]) -> Option<Self> {
+        let key_pair = EcdsaKeyPair::from_private_key_and_public_key(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            private_key,
+            public_key,
+        )
+        .ok()?;
+        let mut secret = [0u8; SECRET_LEN];
+        secret.copy_from_slice(private_key);
+        Some(Self {
+            key_pair: SigningKeyPair::EcdsaP256(key_pair),
+            secret,
+        })
+    }
+
+    /// Generate Ed25519 key from the operating system's secure random generator
+    ///
+    /// # Panics
+    /// If the operating system fails to provide random bytes
+    pub fn generate() -> Self {
+        Self::generate_with_algorithm(Algorithm::Ed25519)
+    }
+
+    /// Generate key of `algorithm`, see `generate`
+    pub fn generate_with_algorithm(algorithm: Algorithm) -> Self {
+        let rng = SystemRandom::new();
+        match algorithm {
+            Algorithm::Ed25519 => {
+                let mut seed = Zeroizing::new([0u8; SECRET_LEN]);
+                rng.fill(&mut *seed).expect(RNG_ERROR);
+                Self::from_bytes(&*seed).expect("Any 32 bytes is a valid Ed25519 seed")
+            }
+            Algorithm::EcdsaP256Sha256 => {
+                let document = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                    .expect(RNG_ERROR);
+                Self::from_pkcs8_der(document.as_ref()).expect("Fail to parse generated key")
+            }
+        }
+    }
+
+    /// Derive Ed25519 key from `seed` and `salt` with HKDF-SHA256
+    ///
+    /// The same input always gives the same key, which is meant for reproducible test fixtures.
+    /// `seed` must be secret and uniformly random, this isn't a password hashing function.
+    pub fn derive(seed: &[u8], salt: &[u8]) -> Self {
+        struct SeedLen;
+        impl hkdf::KeyType for SeedLen {
+            fn len(&self) -> usize {
+                SECRET_LEN
+            }
+        }
+
+        let mut derived = Zeroizing::new([0u8; SECRET_LEN]);
+        hkdf::Salt::new(hkdf::HKDF_SHA256, salt)
+            .extract(seed)
+            .expand(&[DERIVE_INFO], SeedLen)
+            .and_then(|okm| okm.fill(&mut *derived))
+            .expect("HKDF output length is valid");
+        Self::from_bytes(&*derived).expect("Any 32 bytes is a valid Ed25519 seed")
+    }
+
+    /// Public key matching this private key
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::new(self.algorithm(), self.public_key_bytes())
+            .expect("Key pair has a well formed public key")
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self.key_pair {
+            SigningKeyPair::Ed25519(_) => Algorithm::Ed25519,
+            SigningKeyPair::EcdsaP256(_) => Algorithm::EcdsaP256Sha256,
+        }
+    }
+
+    fn public_key_bytes(&self) -> &[u8] {
+        match &self.key_pair {
+            SigningKeyPair::Ed25519(key_pair) => key_pair.public_key().as_ref(),
+            SigningKeyPair::EcdsaP256(key_pair) => key_pair.public_key().as_ref(),
+        }
+    }
+
+    pub fn from_base64<T: ?Sized + AsRef<[u8]>>(input: &T) -> Option<Self> {
+        base64::decode_config(input, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .map(Zeroizing::new)
+            .and_then(|seed| Self::from_bytes(&seed))
+    }
+
+    /// Encode Ed25519 seed or P-256 private scalar, `from_base64` accepts the Ed25519 one
+    pub fn to_base64(&self) -> Zeroizing<String> {
+        Zeroizing::new(base64::encode_config(self.secret, base64::URL_SAFE_NO_PAD))
+    }
+
+    pub fn dummy() -> Self {
+        Self::from_base64("LTqOJVVt2dtGAAbRhGUm5p1L4yw1UJKIRXalD4V1lhc").unwrap()
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrivateKey")
+            .field("algorithm", &self.algorithm())
+            .field("thumbprint", &self.thumbprint())
+            .finish()
+    }
+}
+
+impl Signer for PrivateKey {
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        match &self.key_pair {
+            SigningKeyPair::Ed25519(key_pair) => Ok(key_pair.sign(message).as_ref().to_vec()),
+            // ECDSA needs random nonce
+            SigningKeyPair::EcdsaP256(key_pair) => key_pair
+                .sign(&SystemRandom::new(), message)
+                .map(|signature| signature.as_ref().to_vec())
+                .map_err(|_| SigningFail),
+        }
+    }
+
+    fn algorithm_name(&self) -> &'static str {
+        self.algorithm().name()
+    }
+}
+
+#[derive(Clone)]
+pub struct PublicKey {
+    algorithm: Algorithm,
+    bytes: Vec<u8>,
+}
+
+impl PublicKey {
+    /// Create Ed25519 public key from 32 bytes
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::new(Algorithm::Ed25519, bytes)
+    }
+
+    /// Create public key of `algorithm`, ECDSA keys are 65 bytes uncompressed points
+    pub fn new(algorithm: Algorithm, bytes: &[u8]) -> Option<Self> {
+        let valid = match algorithm {
+            Algorithm::Ed25519 => bytes.len() == ED25519_PUBLIC_KEY_LEN,
+            Algorithm::EcdsaP256Sha256 => {
+                bytes.len() == P256_PUBLIC_KEY_LEN && bytes[0] == UNCOMPRESSED_POINT_TAG
+            }
+        };
+        if !valid {
+            return None;
+        }
+        Some(Self {
+            algorithm,
+            bytes: bytes.to_vec(),
+        })
+    }
+
+    pub fn from_base64<T: ?Sized + AsRef<[u8]>>(input: &T) -> Option<Self> {
+        base64::decode_config(input, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|bytes| Self::from_bytes(&bytes))
+    }
+
+    pub fn to_base64(&self) -> String {
+        base64::encode_config(&self.bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        UnparsedPublicKey::new(self.algorithm.verification_algorithm(), &self.bytes)
+            .verify(message, signature)
+            .is_ok()
+    }
+
+    pub fn dummy() -> Self {
+        Self::from_base64("NqfvZLnVTKSatLSJlfRvZ2QHcD56A7ADRIHSFfyPoEk").unwrap()
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PublicKey")
+            .field("algorithm", &self.algorithm)
+            .field("thumbprint", &self.thumbprint())
+            .finish()
+    }
+}
+
+/// Symmetric key for HMAC-SHA256 tags
+///
+/// Meant for a service which both issues and validates its own tokens. Tags are 32 bytes while
+/// signatures of every `Algorithm` are 64 bytes, so a message tagged by a secret key is never
+/// accepted by a public key and the other way around.
+#[derive(Clone)]
+pub struct SecretKey(hmac::Key);
+
+impl SecretKey {
+    /// Create key from at least 32 bytes of secret
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < MIN_SECRET_KEY_LEN {
+            return None;
+        }
+        Some(Self(hmac::Key::new(hmac::HMAC_SHA256, bytes)))
+    }
+
+    pub fn from_base64<T: ?Sized + AsRef<[u8]>>(input: &T) -> Option<Self> {
+        base64::decode_config(input, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .map(Zeroizing::new)
+            .and_then(|bytes| Self::from_bytes(&bytes))
+    }
+
+    /// Check `tag` of `message` in constant time
+    pub fn verify(&self, message: &[u8], tag: &[u8]) -> bool {
+        hmac::verify(&self.0, message, tag).is_ok()
+    }
+}
+
+impl Signer for SecretKey {
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(hmac::sign(&self.0, message).as_ref().to_vec())
+    }
+
+    fn algorithm_name(&self) -> &'static str {
+        HMAC_SHA256_NAME
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = hmac::sign(&self.0, SECRET_FINGERPRINT_INFO);
+        f.debug_struct("SecretKey")
+            .field("fingerprint", &hex(&tag.as_ref()[..FINGERPRINT_LEN]))
+            .finish()
+    }
+}
+
+/// Short SHA-256 digest of public `bytes` to tell keys apart in logs
+pub(crate) fn fingerprint(bytes: &[u8]) -> String {
+    hex(&digest::digest(&digest::SHA256, bytes).as_ref()[..FINGERPRINT_LEN])
+}
+
+/// Set of trusted public keys indexed by key id
+///
+/// Several keys can be active at the same time, so an identity server can start signing with a new
+/// key while tokens signed by the previous one are still accepted until they expire.
+#[derive(Clone, Debug, Default)]
+pub struct KeySet(BTreeMap<String, PublicKey>);
+
+impl Deref for KeySet {
+    type Target = BTreeMap<String, PublicKey>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for KeySet {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl KeySet {
+    pub fn new() -> Self {
+        Self(Default::default())
+    }
+
+    /// Create a key set holding a single key registered under its thumbprint
+    pub fn single(key: PublicKey) -> Self {
+        let mut key_set = Self::new();
+        key_set.add(key);
+        key_set
+    }
+
+    /// Register `key` under its thumbprint and return that id
+    ///
+    /// An issuer signing with `PrivateKey::thumbprint` as key id needs no configured ids.
+    pub fn add(&mut self, key: PublicKey) -> String {
+        let key_id = key.thumbprint();
+        self.insert(key_id.clone(), key);
+        key_id
+    }
+}
+
+impl<K: Into<String>> FromIterator<(K, PublicKey)> for KeySet {
+    fn from_iter<I: IntoIterator<Item = (K, PublicKey)>>(iter: I) -> Self {
+        Self(iter.into_iter().map(|(id, key)| (id.into(), key)).collect())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Bytes of hex, e.g. of a test vector
+    pub fn hex(input: &str) -> Vec<u8> {
+        (0..input.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&input[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    pub fn get_test_public_key() -> String {
+        String::from("y9OTFvZmHe41kMjCYtDd8574bv46CSDKexUKN9R7mgM")
+    }
+
+    pub fn get_test_private_key() -> String {
+        String::from("aMWX1G0p36BRx7YqAJaBJ7hnMDxqIbln0toRQcWQfoA")
+    }
+
+    pub fn get_test_secret_key() -> String {
+        String::from("Gzm-7MzeaOPpv8JEFnwZOBp1UzQ7fih9RiSEPynwQ0g")
+    }
+
+    /// P-256 uncompressed public key
+    pub fn get_test_ecdsa_public_key() -> String {
+        String::from("BGTf3wr9T0oG18XUnZGHk_YrPpYVYOQZI0VSddxwjuDZP8-kwy3t0V6Ov-CWgxIhqfmhcPvr9XtrQISxKUmh7eI")
+    }
+
+    /// P-256 private scalar
+    pub fn get_test_ecdsa_private_key() -> String {
+        String::from("AaZr9uGdloGVn2jcMAt3ys73TQTYoRxd8UUX-e41Azc")
+    }
+
+    pub fn ecdsa_test_keys() -> (PrivateKey, PublicKey) {
+        let decode = |s: String| base64::decode_config(s, base64::URL_SAFE_NO_PAD).unwrap();
+        let public_key = decode(get_test_ecdsa_public_key());
+        let private_key =
+            PrivateKey::from_ecdsa_p256_bytes(&decode(get_test_ecdsa_private_key()), &public_key)
+                .unwrap();
+        (
+            private_key,
+            PublicKey::new(Algorithm::EcdsaP256Sha256, &public_key).unwrap(),
+        )
+    }
+
+    /// Stand-in for an external signer such as a KMS
+    pub struct MockSigner {
+        key: PrivateKey,
+        available: bool,
+    }
+
+    impl MockSigner {
+        pub fn new(key: PrivateKey) -> Self {
+            Self {
+                key,
+                available: true,
+            }
+        }
+
+        /// Signer whose every call fails like an unreachable signing service
+        pub fn unavailable() -> Self {
+            Self {
+                key: PrivateKey::dummy(),
+                available: false,
+            }
+        }
+    }
+
+    impl Signer for MockSigner {
+        fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+            if self.available {
+                self.key.sign(message)
+            } else {
+                Err(SigningFail)
+            }
+        }
+
+        fn algorithm_name(&self) -> &'static str {
+            self.key.algorithm_name()
+        }
+    }
+
+    #[test]
+    fn test_dummy_key() {
+        // Should not panic
+        PublicKey::dummy();
+        PrivateKey::dummy();
+    }
+
+    #[test]
+    fn test_generate() {
+        for algorithm in [Algorithm::Ed25519, Algorithm::EcdsaP256Sha256].iter() {
+            let private_key = PrivateKey::generate_with_algorithm(*algorithm);
+            let public_key = private_key.public_key();
+            assert_eq!(public_key.algorithm(), *algorithm);
+            assert!(public_key.verify(b"message", &private_key.sign(b"message").unwrap()));
+
+            let other = PrivateKey::generate_with_algorithm(*algorithm);
+            assert_ne!(other.secret, private_key.secret);
+        }
+        assert_eq!(PrivateKey::generate().algorithm(), Algorithm::Ed25519);
+    }
+
+    #[test]
+    fn test_public_key() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        assert_eq!(private_key.public_key().to_base64(), get_test_public_key());
+        assert_eq!(*private_key.to_base64(), get_test_private_key());
+
+        let (private_key, public_key) = ecdsa_test_keys();
+        assert_eq!(private_key.public_key().as_bytes(), public_key.as_bytes());
+    }
+
+    #[test]
+    fn test_derive() {
+        let key1 = PrivateKey::derive(b"fixture seed", b"salt");
+        let key2 = PrivateKey::derive(b"fixture seed", b"salt");
+        assert_eq!(key1.secret, key2.secret);
+        assert_ne!(
+            PrivateKey::derive(b"fixture seed", b"other salt").secret,
+            key1.secret
+        );
+        assert_ne!(
+            PrivateKey::derive(b"other seed", b"salt").secret,
+            key1.secret
+        );
+
+        // derivation must never change, fixtures depend on it
+        assert_eq!(
+            *key1.to_base64(),
+            "xKtgXGpjZtvYnxeae1DWcX1I2bIq6Lgn3oc4GYtEE6M"
+        );
+    }
+
+    #[test]
+    fn test_ecdsa_sign_verify() {
+        let (private_key, public_key) = ecdsa_test_keys();
+        assert_eq!(private_key.algorithm(), Algorithm::EcdsaP256Sha256);
+        let signature = private_key.sign(b"message").unwrap();
+        assert!(public_key.verify(b"message", &signature));
+        assert!(!public_key.verify(b"other message", &signature));
+    }
+
+    #[test]
+    fn test_algorithm_confusion() {
+        let (ecdsa_private_key, ecdsa_public_key) = ecdsa_test_keys();
+        let ed25519_private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let ed25519_public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+
+        // both signatures are 64 bytes, but each verifies only by the algorithm of its key
+        assert!(
+            !ecdsa_public_key.verify(b"message", &ed25519_private_key.sign(b"message").unwrap())
+        );
+        assert!(
+            !ed25519_public_key.verify(b"message", &ecdsa_private_key.sign(b"message").unwrap())
+        );
+
+        // key bytes of one algorithm aren't a key of the other
+        assert!(
+            PublicKey::new(Algorithm::EcdsaP256Sha256, ed25519_public_key.as_bytes()).is_none()
+        );
+        assert!(PublicKey::new(Algorithm::Ed25519, ecdsa_public_key.as_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_malformed_public_key() {
+        let (_, ecdsa_public_key) = ecdsa_test_keys();
+        let point = ecdsa_public_key.as_bytes();
+        // truncated point
+        assert!(PublicKey::new(Algorithm::EcdsaP256Sha256, &point[..33]).is_none());
+        assert!(PublicKey::new(Algorithm::EcdsaP256Sha256, &[]).is_none());
+        // compressed point
+        let compressed = [&[0x02], &point[1..33]].concat();
+        assert!(PublicKey::new(Algorithm::EcdsaP256Sha256, &compressed).is_none());
+        let mut tagged = point.to_vec();
+        tagged[0] = 0x03;
+        assert!(PublicKey::new(Algorithm::EcdsaP256Sha256, &tagged).is_none());
+
+        assert!(PublicKey::from_bytes(&[1u8; 31]).is_none());
+        assert!(PublicKey::from_base64("AAAA").is_none());
+    }
+
+    #[test]
+    fn test_secret_key() {
+        let key = SecretKey::from_base64(&get_test_secret_key()).unwrap();
+        let tag = key.sign(b"message").unwrap();
+        assert_eq!(tag.len(), 32);
+        assert!(key.verify(b"message", &tag));
+        assert!(!key.verify(b"other message", &tag));
+        assert!(!key.verify(b"message", &tag[..31]));
+
+        let other = SecretKey::from_bytes(&[1u8; 32]).unwrap();
+        assert!(!other.verify(b"message", &tag));
+
+        // too short
+        assert!(SecretKey::from_bytes(&[1u8; 31]).is_none());
+    }
+
+    #[test]
+    fn test_debug_should_not_show_key_material() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let debug = format!("{:?}", private_key);
+        assert!(!debug.contains(&get_test_private_key()));
+        assert!(!debug.contains(&get_test_public_key()));
+        assert!(!debug.contains(&format!("{:?}", private_key.secret)));
+        // private key shows the thumbprint of its public key
+        let thumbprint = private_key.thumbprint();
+        assert!(debug.contains(&thumbprint));
+        assert!(format!("{:?}", private_key.public_key()).contains(&thumbprint));
+
+        let secret_key = SecretKey::from_base64(&get_test_secret_key()).unwrap();
+        let debug = format!("{:?}", secret_key);
+        assert!(!debug.contains(&get_test_secret_key()));
+        assert_ne!(
+            debug,
+            format!("{:?}", SecretKey::from_bytes(&[1u8; 32]).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_key_set() {
+        let key_set: KeySet = vec![("k1", PublicKey::dummy()), ("k2", PublicKey::dummy())]
+            .into_iter()
+            .collect();
+        assert_eq!(key_set.len(), 2);
+        assert!(key_set.get("k1").is_some());
+        assert!(key_set.get("k3").is_none());
+
+        let key_set = KeySet::single(PublicKey::dummy());
+        assert!(key_set.contains_key(&PublicKey::dummy().thumbprint()));
+
+        let mut key_set = KeySet::new();
+        let (_, ecdsa_public_key) = ecdsa_test_keys();
+        let key_id = key_set.add(ecdsa_public_key.clone());
+        assert_eq!(key_id, ecdsa_public_key.thumbprint());
+        assert_eq!(key_set.add(ecdsa_public_key), key_id);
+        assert_eq!(key_set.len(), 1);
+    }
+}
+
+mod der;
+mod encryption;
+mod jwk;
+mod pem;
+mod pkcs8;
+mod traits;
@@ -0,0 +1,79 @@
+//! Textual encoding (RFC 7468) of DER encoded keys
+//!
+//! Every buffer is zeroized as it may hold a private key.
+
+use zeroize::Zeroizing;
+
+const LINE_LEN: usize = 64;
+
+pub fn encode(label: &str, der: &[u8]) -> Zeroizing<String> {
+    let body = Zeroizing::new(base64::encode_config(der, base64::STANDARD));
+    let begin = format!("-----BEGIN {}-----\n", label);
+    let end = format!("-----END {}-----\n", label);
+    // allocate once, growing would leave copies of the content behind
+    let lines = (body.len() + LINE_LEN - 1) / LINE_LEN;
+    let mut pem = Zeroizing::new(String::with_capacity(
+        begin.len() + body.len() + lines + end.len(),
+    ));
+    pem.push_str(&begin);
+    for line in body.as_bytes().chunks(LINE_LEN) {
+        // base64 output is always ascii
+        pem.push_str(std::str::from_utf8(line).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str(&end);
+    pem
+}
+
+/// Decode first PEM block found in `input` and return its label together with DER content
+///
+/// Explanatory text around the block is ignored.
+pub fn decode(input: &str) -> Option<(&str, Zeroizing<Vec<u8>>)> {
+    const BEGIN: &str = "-----BEGIN ";
+    const END: &str = "-----END ";
+    const DASHES: &str = "-----";
+
+    let (_, rest) = input.split_once(BEGIN)?;
+    let (label, rest) = rest.split_once(DASHES)?;
+    let (body, rest) = rest.split_once(END)?;
+    let (end_label, _) = rest.split_once(DASHES)?;
+    if label != end_label {
+        return None;
+    }
+    let body: Zeroizing<String> = Zeroizing::new(body.split_whitespace().collect());
+    let der = base64::decode_config(&*body, base64::STANDARD).ok()?;
+    Some((label, Zeroizing::new(der)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let der = vec![7u8; 100];
+        let pem = encode("PUBLIC KEY", &der);
+        assert!(pem.starts_with("-----BEGIN PUBLIC KEY-----\n"));
+        assert!(pem.ends_with("-----END PUBLIC KEY-----\n"));
+        assert!(pem.lines().all(|line| line.len() <= LINE_LEN));
+
+        let (label, decoded) = decode(&pem).unwrap();
+        assert_eq!(label, "PUBLIC KEY");
+        assert_eq!(*decoded, der);
+        assert_eq!(pem.capacity(), pem.len());
+    }
+
+    #[test]
+    fn decode_should_ignore_surrounding_text() {
+        let pem = "Subject: test\r\n-----BEGIN X-----\r\nAQID\r\n-----END X-----\r\ntrailer";
+        assert_eq!(decode(pem), Some(("X", Zeroizing::new(vec![1, 2, 3]))));
+    }
+
+    #[test]
+    fn decode_should_reject_malformed() {
+        assert_eq!(decode("AQID"), None);
+        assert_eq!(decode("-----BEGIN X-----\nAQID\n-----END Y-----\n"), None);
+        assert_eq!(decode("-----BEGIN X-----\nAQ!D\n-----END X-----\n"), None);
+        assert_eq!(decode("-----BEGIN X-----\nAQID\n"), None);
+    }
+}
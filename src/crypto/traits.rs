@@ -0,0 +1,14 @@
+use crate::error::Error;
+
+/// Signs messages on behalf of an issuer
+///
+/// `PrivateKey` and `SecretKey` sign in process. Implementations may delegate to an external
+/// signer such as a KMS or an HSM, so the private key never has to be loaded into memory.
+pub trait Signer {
+    /// Sign `message`, a failure of the underlying signer is reported as `Error::SigningFail`
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// JOSE name (RFC 7518) of the signature algorithm, e.g. `EdDSA`, which is recorded in the
+    /// header of signed messages
+    fn algorithm_name(&self) -> &'static str;
+}
@@ -0,0 +1,31 @@
+//! URL-safe base64 without padding, the encoding of every token segment and JWK member, and hex
+
+pub(crate) fn b64enc(input: &[u8]) -> String {
+    base64::encode_config(input, base64::URL_SAFE_NO_PAD)
+}
+
+/// Decode unpadded base64url, padding is tolerated
+pub(crate) fn b64dec(input: &str) -> Option<Vec<u8>> {
+    base64::decode_config(input, base64::URL_SAFE_NO_PAD).ok()
+}
+
+/// Same as `b64dec` except that padding is rejected, as PASETO requires
+pub(crate) fn b64dec_strict(input: &str) -> Option<Vec<u8>> {
+    if input.contains('=') {
+        return None;
+    }
+    b64dec(input)
+}
+
+/// Length of the bytes encoded by an unpadded base64 `segment`, so a payload is bounded before
+/// it's decoded
+pub(crate) fn decoded_len(segment: Option<&str>) -> usize {
+    let len = segment.map_or(0, str::len);
+    // every 4 characters encode 3 bytes, 2 or 3 trailing characters encode 1 or 2 bytes
+    len / 4 * 3 + (len % 4).saturating_sub(1)
+}
+
+/// Lowercase hex of `bytes`
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -1,9 +1,24 @@
 #[derive(Debug, Clone)]
 pub enum Error {
     SignatureVerificationFail,
+    SigningFail,
+    EncryptionFail,
+    DecryptionFail,
+    UnknownKeyId,
+    InsufficientSignatures,
+    BadRequestSignature,
+    StaleRequestSignature,
     BadAccessTokenEncoding,
     BadSignedMessageEncoding,
+    BadKeyEncoding,
+    LimitExceeded,
+    UnsupportedKeyAlgorithm,
     Forbidden,
     ExpiredAccessToken,
+    NotYetValidAccessToken,
+    UnexpectedIssuer,
+    UnexpectedAudience,
+    Revoked,
+    Replayed,
     Unauthorized,
 }
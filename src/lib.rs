@@ -1,8 +1,12 @@
 pub use error::Error;
 
+pub mod cose;
 pub mod crypto;
+mod encoding;
 mod error;
+pub mod http;
 pub mod message;
+pub mod paseto;
 pub mod rbac;
 pub mod token;
 
@@ -1,29 +1,509 @@
-use crate::crypto::{PrivateKey, PublicKey};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use ring::digest;
+
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::crypto::{fingerprint, DecryptionKey, EncryptionKey, PublicKey, SecretKey, Signer};
+use crate::encoding::{b64dec, b64enc, decoded_len};
+use crate::error::Error::{self, *};
+use crate::token::Limits;
 
 const SEPARATOR: &str = ".";
+/// Separates the signatures of a `MultiSignedMessage`, never part of a `SignedMessage`
+pub const SIGNATURE_SEPARATOR: &str = "~";
+/// Version of the header format, messages of any other version are rejected
+pub const HEADER_VERSION: u8 = 1;
+const VERSION_FIELD: &str = "v";
+/// `typ` of JWS messages, which carry JWT claims
+const JWT_TYPE: &str = "JWT";
+/// `typ` of the signatures of a `MultiSignedMessage`, which a `SignedMessage` never has
+pub const MULTI_SIGNATURE_TYPE: &str = "multi";
+/// `dig` of detached messages, which sign the SHA-256 digest of their payload
+const DETACHED_DIGEST: &str = "S256";
+/// Longest signature of the supported algorithms, Ed25519 and ECDSA P-256 signatures
+const MAX_SIGNATURE_LEN: usize = 64;
+
+/// Header of a signed message, covered by the signature together with the message
+///
+/// Encoded as compact JSON, e.g. `{"v":1,"alg":"EdDSA","kid":"k1"}`. Unknown fields are rejected
+/// so a header can't carry data which is ignored by older validators.
+///
+/// A header without version is a JWS protected header (RFC 7515), e.g. `{"alg":"EdDSA"}`. As
+/// required by JWS its unknown parameters are ignored, unless they're marked critical.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Header {
+    /// `None` for JWS messages
+    #[serde(rename = "v", default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u8>,
+    /// JOSE name of the signature algorithm, see `Signer::algorithm_name`
+    pub alg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    /// Type of the signed message, e.g. the kind of access token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    /// Digest algorithm of a detached payload, `None` when the payload is embedded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dig: Option<String>,
+    /// Thumbprint of the `EncryptionKey` an encrypted message is meant for, see
+    /// `SignedMessage::create_for_recipient`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rcp: Option<String>,
+}
+
+/// JWS protected header parameters which are understood
+#[derive(Deserialize)]
+struct JwsHeader {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    typ: Option<String>,
+    #[serde(default)]
+    crit: Option<serde_json::Value>,
+}
+
+impl Header {
+    fn decode(segment: &str) -> Option<Self> {
+        let json = b64dec(segment)?;
+        let fields: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(&json).ok()?;
+        if fields.contains_key(VERSION_FIELD) {
+            let header: Self = serde_json::from_slice(&json).ok()?;
+            return Some(header).filter(|header| header.version == Some(HEADER_VERSION));
+        }
+        let header: JwsHeader = serde_json::from_slice(&json).ok()?;
+        // critical extensions would have to be understood, none is
+        if header.crit.is_some() {
+            return None;
+        }
+        Some(Self {
+            version: None,
+            alg: header.alg,
+            kid: header.kid,
+            typ: header.typ,
+            dig: None,
+            rcp: None,
+        })
+    }
+}
+
+/// Header of a `SignedMessageRef`, borrowed from the decode buffer
+///
+/// Same rules as `Header`, except that strings with JSON escapes are rejected as they can't be
+/// borrowed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeaderRef<'a> {
+    pub version: Option<u8>,
+    pub alg: &'a str,
+    pub kid: Option<&'a str>,
+    pub typ: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VersionedHeaderRef<'a> {
+    #[serde(rename = "v")]
+    version: u8,
+    alg: &'a str,
+    #[serde(default)]
+    kid: Option<&'a str>,
+    #[serde(default)]
+    typ: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct JwsHeaderRef<'a> {
+    #[serde(rename = "v", default)]
+    version: Present,
+    alg: &'a str,
+    #[serde(default)]
+    kid: Option<&'a str>,
+    #[serde(default)]
+    typ: Option<&'a str>,
+    #[serde(default)]
+    crit: Present,
+}
+
+/// Whether a field is present, even if it's `null`
+#[derive(Default)]
+struct Present(bool);
+
+impl<'de> Deserialize<'de> for Present {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IgnoredAny::deserialize(deserializer).map(|_| Present(true))
+    }
+}
+
+impl<'a> HeaderRef<'a> {
+    fn decode(json: &'a [u8]) -> Option<Self> {
+        let header: JwsHeaderRef = serde_json::from_slice(json).ok()?;
+        if header.version.0 {
+            let header: VersionedHeaderRef = serde_json::from_slice(json).ok()?;
+            return Some(Self {
+                version: Some(header.version),
+                alg: header.alg,
+                kid: header.kid,
+                typ: header.typ,
+            })
+            .filter(|header| header.version == Some(HEADER_VERSION));
+        }
+        if header.crit.0 {
+            return None;
+        }
+        Some(Self {
+            version: None,
+            alg: header.alg,
+            kid: header.kid,
+            typ: header.typ,
+        })
+    }
+}
+
+/// Signed envelope of a token, whatever its wire format
+///
+/// Associated data is context which the signature covers but the token doesn't carry, e.g. the
+/// audience hostname. Verification fails unless it's the data the token was signed with, empty
+/// associated data being the same as none.
+pub trait Envelope {
+    /// Id of the key which signed the message, if the envelope carries one
+    fn key_id(&self) -> Option<Cow<'_, str>>;
+    fn verify(&self, key: &PublicKey, associated_data: &[u8]) -> bool;
+    fn verify_hmac(&self, key: &SecretKey, associated_data: &[u8]) -> bool;
+    /// Signed message, which is only trusted once verified
+    fn message(&self) -> &[u8];
+}
+
+/// Header and the exact segment it was decoded from, which is what the signature covers
+struct EncodedHeader {
+    header: Header,
+    segment: String,
+}
+
+/// Payload of a detached message, hashed as it's streamed so it's never buffered whole
+///
+/// Feed it by `update`, as an `io::Write` or from an `io::Read`.
+#[derive(Clone)]
+pub struct DetachedPayload(digest::Context);
+
+impl DetachedPayload {
+    pub fn new() -> Self {
+        Self(digest::Context::new(&digest::SHA256))
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.0.update(chunk);
+    }
+
+    /// Read `reader` to its end
+    pub fn from_reader(mut reader: impl Read) -> io::Result<Self> {
+        let mut payload = Self::new();
+        io::copy(&mut reader, &mut payload)?;
+        Ok(payload)
+    }
+
+    pub(crate) fn finish(self) -> digest::Digest {
+        self.0.finish()
+    }
+}
+
+impl Default for DetachedPayload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for DetachedPayload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
+impl fmt::Debug for DetachedPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DetachedPayload").finish()
+    }
+}
+
+/// Message signed by a `Signer`, or tagged by a secret key in symmetric mode
+///
+/// Encoded as `header.message.signature`, every segment is URL-safe base64 without padding and
+/// the signature covers `header.message` as encoded. Messages of the legacy `message.signature`
+/// format, without header, are still decoded and verified, see
+/// `ValidationAuthority::reject_legacy_tokens`.
+///
+/// This is the layout of JWS compact serialization, so messages of `create_jws` are JWS (RFC
+/// 7515) understood by stock JOSE libraries and JWS of other issuers are verified as well.
+///
+/// A detached message, see `create_detached`, leaves its payload out and is encoded as
+/// `header..signature`.
 pub struct SignedMessage {
+    /// `None` for legacy messages
+    header: Option<EncodedHeader>,
     message: Vec<u8>,
     signature: Vec<u8>,
 }
 
+/// Signing input of encoded `segments`, followed by a segment of associated data if there's any
+fn with_associated_data(mut segments: String, associated_data: &[u8]) -> Vec<u8> {
+    if !associated_data.is_empty() {
+        segments.push_str(SEPARATOR);
+        segments.push_str(&b64enc(associated_data));
+    }
+    segments.into_bytes()
+}
+
 impl SignedMessage {
     pub fn encode(&self) -> String {
-        fn b64enc(input: &[u8]) -> String {
-            base64::encode_config(input, base64::URL_SAFE_NO_PAD)
+        let message = if self.is_detached() {
+            String::new()
+        } else {
+            b64enc(&self.message)
+        };
+        let signature = b64enc(&self.signature);
+        match &self.header {
+            Some(header) => [header.segment.as_str(), &message, &signature].join(SEPARATOR),
+            None => [message, signature].join(SEPARATOR),
         }
-        [b64enc(&self.message), b64enc(&self.signature)].join(SEPARATOR)
     }
 
-    pub fn create(message: Vec<u8>, key: &PrivateKey) -> Self {
-        let signature = key.sign(&message);
-        Self { message, signature }
+    /// Sign message by `signer`, which is a `SecretKey` in symmetric mode
+    pub fn create<S: Signer + ?Sized>(message: Vec<u8>, signer: &S) -> Result<Self, Error> {
+        Self::create_with_header(message, signer, None, None)
+    }
+
+    /// Sign message and embed `key_id` so validators can pick the matching public key
+    ///
+    /// Validators register keys without an explicit id under their thumbprint, so
+    /// `PrivateKey::thumbprint` is the id to use unless another one is configured.
+    pub fn create_with_key_id<S: Signer + ?Sized>(
+        message: Vec<u8>,
+        signer: &S,
+        key_id: impl Into<String>,
+    ) -> Result<Self, Error> {
+        Self::create_with_header(message, signer, Some(key_id.into()), None)
     }
 
+    /// Sign message with optional key id and type in its header
+    pub fn create_with_header<S: Signer + ?Sized>(
+        message: Vec<u8>,
+        signer: &S,
+        key_id: Option<String>,
+        token_type: Option<String>,
+    ) -> Result<Self, Error> {
+        let header = Header {
+            version: Some(HEADER_VERSION),
+            alg: signer.algorithm_name().to_owned(),
+            kid: key_id,
+            typ: token_type,
+            dig: None,
+            rcp: None,
+        };
+        Self::sign_with_header(message, signer, header, &[])
+    }
+
+    /// Sign message bound to `associated_data`, which isn't encoded in the message and has to be
+    /// handed to `verify_with_associated_data`, e.g. the audience hostname or a session id
+    ///
+    /// The associated data is signed as an extra segment, so such a message isn't a JWS which
+    /// JOSE libraries could verify.
+    pub fn create_with_associated_data<S: Signer + ?Sized>(
+        message: Vec<u8>,
+        signer: &S,
+        key_id: Option<String>,
+        associated_data: &[u8],
+    ) -> Result<Self, Error> {
+        let header = Header {
+            version: Some(HEADER_VERSION),
+            alg: signer.algorithm_name().to_owned(),
+            kid: key_id,
+            typ: None,
+            dig: None,
+            rcp: None,
+        };
+        Self::sign_with_header(message, signer, header, associated_data)
+    }
+
+    /// Sign message for `recipient`, whose thumbprint is put in the header so the message can
+    /// only be encrypted to that key, see `encrypt`
+    pub fn create_for_recipient<S: Signer + ?Sized>(
+        message: Vec<u8>,
+        signer: &S,
+        key_id: Option<String>,
+        recipient: &EncryptionKey,
+    ) -> Result<Self, Error> {
+        let header = Header {
+            version: Some(HEADER_VERSION),
+            alg: signer.algorithm_name().to_owned(),
+            kid: key_id,
+            typ: None,
+            dig: None,
+            rcp: Some(recipient.thumbprint()),
+        };
+        Self::sign_with_header(message, signer, header, &[])
+    }
+
+    /// Sign JWT claims as JWS, e.g. `{"alg":"EdDSA","kid":"k1","typ":"JWT"}` when signed by an
+    /// Ed25519 key, so the message is readable by JOSE libraries
+    pub fn create_jws<S: Signer + ?Sized>(
+        claims: Vec<u8>,
+        signer: &S,
+        key_id: Option<String>,
+    ) -> Result<Self, Error> {
+        let header = Header {
+            version: None,
+            alg: signer.algorithm_name().to_owned(),
+            kid: key_id,
+            typ: Some(JWT_TYPE.to_owned()),
+            dig: None,
+            rcp: None,
+        };
+        Self::sign_with_header(claims, signer, header, &[])
+    }
+
+    /// Sign the SHA-256 digest of `payload`, which is left out of the message and has to be
+    /// handed to `verify_detached`, e.g. a request body or a file
+    pub fn create_detached<S: Signer + ?Sized>(
+        payload: DetachedPayload,
+        signer: &S,
+        key_id: Option<String>,
+    ) -> Result<Self, Error> {
+        let header = Header {
+            version: Some(HEADER_VERSION),
+            alg: signer.algorithm_name().to_owned(),
+            kid: key_id,
+            typ: None,
+            dig: Some(DETACHED_DIGEST.to_owned()),
+            rcp: None,
+        };
+        let digest = payload.finish();
+        let mut signed_message =
+            Self::sign_with_header(digest.as_ref().to_vec(), signer, header, &[])?;
+        signed_message.message.clear();
+        Ok(signed_message)
+    }
+
+    fn sign_with_header<S: Signer + ?Sized>(
+        message: Vec<u8>,
+        signer: &S,
+        header: Header,
+        associated_data: &[u8],
+    ) -> Result<Self, Error> {
+        let segment = b64enc(&serde_json::to_vec(&header).expect("Fail serialize header"));
+        let mut signed_message = Self {
+            header: Some(EncodedHeader { header, segment }),
+            message,
+            signature: Vec::new(),
+        };
+        let input = signed_message
+            .signing_input(associated_data)
+            .expect("Message with header signs associated data");
+        signed_message.signature = signer.sign(&input)?;
+        Ok(signed_message)
+    }
+
+    /// `None` for messages of the legacy format
+    pub fn header(&self) -> Option<&Header> {
+        self.header.as_ref().map(|encoded| &encoded.header)
+    }
+
+    pub fn key_id(&self) -> Option<&str> {
+        self.header()?.kid.as_deref()
+    }
+
+    /// Bytes covered by the signature, `None` if associated data is given for a legacy message,
+    /// which can't carry it
+    fn signing_input(&self, associated_data: &[u8]) -> Option<Cow<'_, [u8]>> {
+        match &self.header {
+            Some(header) => {
+                let segments = [header.segment.as_str(), &b64enc(&self.message)].join(SEPARATOR);
+                Some(Cow::Owned(with_associated_data(segments, associated_data)))
+            }
+            None if associated_data.is_empty() => Some(Cow::Borrowed(&self.message)),
+            None => None,
+        }
+    }
+
+    /// Algorithm of the header must be the one of the verifying key, legacy messages have none
+    fn algorithm_is(&self, name: &str) -> bool {
+        self.header().map_or(true, |header| header.alg == name)
+    }
+
+    /// Detached messages are only verified by `verify_detached`
     pub fn verify(&self, key: &PublicKey) -> bool {
-        key.verify(&self.message, &self.signature)
+        self.verify_with_associated_data(key, &[])
     }
 
+    pub fn verify_hmac(&self, key: &SecretKey) -> bool {
+        self.verify_hmac_with_associated_data(key, &[])
+    }
+
+    /// Verify message of `create_with_associated_data`
+    pub fn verify_with_associated_data(&self, key: &PublicKey, associated_data: &[u8]) -> bool {
+        !self.is_detached()
+            && self.algorithm_is(key.algorithm().name())
+            && self
+                .signing_input(associated_data)
+                .is_some_and(|input| key.verify(&input, &self.signature))
+    }
+
+    pub fn verify_hmac_with_associated_data(
+        &self,
+        key: &SecretKey,
+        associated_data: &[u8],
+    ) -> bool {
+        !self.is_detached()
+            && self.algorithm_is(key.algorithm_name())
+            && self
+                .signing_input(associated_data)
+                .is_some_and(|input| key.verify(&input, &self.signature))
+    }
+
+    /// Whether the payload is left out of the message, see `create_detached`
+    pub fn is_detached(&self) -> bool {
+        self.header().is_some_and(|header| header.dig.is_some())
+    }
+
+    /// Bytes covered by the signature of a detached message, `None` for any other message
+    fn detached_signing_input(&self, payload: DetachedPayload) -> Option<Vec<u8>> {
+        let header = self.header.as_ref()?;
+        if header.header.dig.as_deref() != Some(DETACHED_DIGEST) {
+            return None;
+        }
+        let digest = b64enc(payload.finish().as_ref());
+        Some(
+            [header.segment.as_str(), &digest]
+                .join(SEPARATOR)
+                .into_bytes(),
+        )
+    }
+
+    /// Verify detached message against its `payload`
+    pub fn verify_detached(&self, payload: DetachedPayload, key: &PublicKey) -> bool {
+        self.algorithm_is(key.algorithm().name())
+            && self
+                .detached_signing_input(payload)
+                .is_some_and(|input| key.verify(&input, &self.signature))
+    }
+
+    pub fn verify_detached_hmac(&self, payload: DetachedPayload, key: &SecretKey) -> bool {
+        self.algorithm_is(key.algorithm_name())
+            && self
+                .detached_signing_input(payload)
+                .is_some_and(|input| key.verify(&input, &self.signature))
+    }
+
+    /// Empty for detached messages
     pub fn message(&self) -> &[u8] {
         &self.message
     }
@@ -32,42 +512,683 @@ impl SignedMessage {
         &self.signature
     }
 
+    /// Encrypt encoded message to `recipient` so only the holder of the matching `DecryptionKey`
+    /// can read the payload, encoded as a single URL-safe base64 segment
+    ///
+    /// The message is signed before it's encrypted, so the recipient still verifies the issuer.
+    /// It must be signed for `recipient` by `create_for_recipient`, otherwise `EncryptionFail`.
+    pub fn encrypt(&self, recipient: &EncryptionKey) -> Result<String, Error> {
+        if !self.is_for_recipient(recipient) {
+            return Err(EncryptionFail);
+        }
+        let ciphertext = recipient.encrypt(self.encode().as_bytes())?;
+        Ok(b64enc(&ciphertext))
+    }
+
+    /// Decrypt and decode message of `encrypt` within `Limits::default()`, the signature is left
+    /// to be verified
+    ///
+    /// A message signed for another recipient is `DecryptionFail`, so a recipient can't
+    /// re-encrypt a message it received to pass it off to another one.
+    pub fn decrypt(s: &str, key: &DecryptionKey) -> Result<Self, Error> {
+        Self::decrypt_with_limits(s, key, &Limits::default())
+    }
+
+    /// Same as `decrypt` within `limits`, which is what `ValidationAuthority` calls
+    ///
+    /// The ciphertext is bounded by `Limits::max_token_len` before it's decrypted, the payload by
+    /// its encoded segment in the plaintext before it's decoded.
+    pub fn decrypt_with_limits(
+        s: &str,
+        key: &DecryptionKey,
+        limits: &Limits,
+    ) -> Result<Self, Error> {
+        limits.check_token_len(s.len())?;
+        let ciphertext = b64dec(s).ok_or(BadSignedMessageEncoding)?;
+        let plaintext = key.decrypt(&ciphertext)?;
+        let plaintext = std::str::from_utf8(&plaintext).map_err(|_| BadSignedMessageEncoding)?;
+        limits.check_payload_len(decoded_len(plaintext.rsplit(SEPARATOR).nth(1)))?;
+        let message = Self::decode(plaintext).ok_or(BadSignedMessageEncoding)?;
+        if message.is_for_recipient(&key.public_key()) {
+            Ok(message)
+        } else {
+            Err(DecryptionFail)
+        }
+    }
+
+    fn is_for_recipient(&self, recipient: &EncryptionKey) -> bool {
+        self.header()
+            .and_then(|header| header.rcp.as_deref())
+            .is_some_and(|rcp| rcp == recipient.thumbprint())
+    }
+
+    /// Decode message, which is rejected if it has extra segments or a header which is malformed
+    /// or of an unknown version, see `Header`
+    ///
+    /// A signature of a `MultiSignedMessage` lifted into a message of its own is rejected too, by
+    /// its `typ`.
     pub fn decode(s: &str) -> Option<Self> {
-        let mut iter = s.split(SEPARATOR);
-        let decode = |input| base64::decode_config(input, base64::URL_SAFE_NO_PAD).ok();
-        match (iter.next(), iter.next()) {
-            (Some(message), Some(signature)) => Some(SignedMessage {
-                message: decode(message)?,
-                signature: decode(signature)?,
-            }),
-            _ => None,
+        let segments: Vec<&str> = s.split(SEPARATOR).collect();
+        let (header, message, signature) = match segments.as_slice() {
+            [message, signature] => (None, message, signature),
+            [segment, message, signature] => {
+                let header = Header::decode(segment)
+                    .filter(|header| header.typ.as_deref() != Some(MULTI_SIGNATURE_TYPE))?;
+                // nothing but the digest of SHA-256 is known, and it's never embedded
+                match header.dig.as_deref() {
+                    Some(DETACHED_DIGEST) if message.is_empty() => (),
+                    None => (),
+                    _ => return None,
+                }
+                let segment = (*segment).to_owned();
+                (Some(EncodedHeader { header, segment }), message, signature)
+            }
+            _ => return None,
+        };
+        Some(SignedMessage {
+            header,
+            message: b64dec(message)?,
+            signature: b64dec(signature)?,
+        })
+    }
+}
+
+impl Envelope for SignedMessage {
+    fn key_id(&self) -> Option<Cow<'_, str>> {
+        self.key_id().map(Cow::Borrowed)
+    }
+
+    fn verify(&self, key: &PublicKey, associated_data: &[u8]) -> bool {
+        self.verify_with_associated_data(key, associated_data)
+    }
+
+    fn verify_hmac(&self, key: &SecretKey, associated_data: &[u8]) -> bool {
+        self.verify_hmac_with_associated_data(key, associated_data)
+    }
+
+    fn message(&self) -> &[u8] {
+        self.message()
+    }
+}
+
+/// Shows a fingerprint of the signature instead of the content, an encoded message is a bearer
+/// credential
+impl fmt::Debug for SignedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignedMessage")
+            .field("header", &self.header())
+            .field("message_len", &self.message.len())
+            .field("signature", &fingerprint(&self.signature))
+            .finish()
+    }
+}
+
+/// Borrowed counterpart of `SignedMessage`, decoded and verified without heap allocation
+///
+/// Header and message are decoded into a buffer of the caller, which may live on the stack, and
+/// the signature into an inline array. The signed bytes are taken from the encoded message itself.
+pub struct SignedMessageRef<'a> {
+    header: Option<HeaderRef<'a>>,
+    /// `header.message` as encoded, `None` for legacy messages which sign the message itself
+    signed_segments: Option<&'a str>,
+    message: &'a [u8],
+    signature: [u8; MAX_SIGNATURE_LEN],
+    signature_len: usize,
+}
+
+/// Capacity base64 decoding needs for `len` encoded bytes
+fn decoded_capacity(len: usize) -> usize {
+    (len + 3) / 4 * 3
+}
+
+/// Decode `input` into the front of `buffer`, returns the decoded bytes and the unused buffer
+fn b64dec_into<'b>(input: &str, buffer: &'b mut [u8]) -> Option<(&'b [u8], &'b mut [u8])> {
+    let capacity = decoded_capacity(input.len());
+    if capacity > buffer.len() {
+        return None;
+    }
+    let (decoded, rest) = buffer.split_at_mut(capacity);
+    let len = base64::decode_config_slice(input, base64::URL_SAFE_NO_PAD, decoded).ok()?;
+    let decoded: &'b [u8] = decoded;
+    Some((&decoded[..len], rest))
+}
+
+impl<'a> SignedMessageRef<'a> {
+    /// Decode message into `buffer`, which is always large enough when it's as long as `s`
+    ///
+    /// Rejects everything `SignedMessage::decode` rejects, a buffer which is too small and
+    /// signatures longer than the ones of the supported algorithms.
+    pub fn decode(s: &'a str, buffer: &'a mut [u8]) -> Option<Self> {
+        let mut segments = s.split(SEPARATOR);
+        let (first, second) = (segments.next()?, segments.next()?);
+        let third = segments.next();
+        if segments.next().is_some() {
+            return None;
+        }
+        let (header, signed_segments, message, signature) = match third {
+            None => (None, None, first, second),
+            Some(signature) => {
+                let signed_segments = &s[..first.len() + SEPARATOR.len() + second.len()];
+                (Some(first), Some(signed_segments), second, signature)
+            }
+        };
+
+        let (header, buffer) = match header {
+            Some(header) => {
+                let (json, rest) = b64dec_into(header, buffer)?;
+                let header = HeaderRef::decode(json)
+                    .filter(|header| header.typ != Some(MULTI_SIGNATURE_TYPE))?;
+                (Some(header), rest)
+            }
+            None => (None, buffer),
+        };
+        let (message, _) = b64dec_into(message, buffer)?;
+        let mut signature_buffer = [0u8; MAX_SIGNATURE_LEN + 2];
+        let (decoded, _) = b64dec_into(signature, &mut signature_buffer)?;
+        let signature_len = decoded.len();
+        if signature_len > MAX_SIGNATURE_LEN {
+            return None;
+        }
+        let mut signature = [0u8; MAX_SIGNATURE_LEN];
+        signature[..signature_len].copy_from_slice(&signature_buffer[..signature_len]);
+        Some(Self {
+            header,
+            signed_segments,
+            message,
+            signature,
+            signature_len,
+        })
+    }
+
+    /// `None` for messages of the legacy format
+    pub fn header(&self) -> Option<&HeaderRef<'a>> {
+        self.header.as_ref()
+    }
+
+    pub fn key_id(&self) -> Option<&'a str> {
+        self.header?.kid
+    }
+
+    /// Signing input, which is only allocated to append associated data
+    fn signing_input(&self, associated_data: &[u8]) -> Option<Cow<'a, [u8]>> {
+        match self.signed_segments {
+            Some(segments) if associated_data.is_empty() => {
+                Some(Cow::Borrowed(segments.as_bytes()))
+            }
+            Some(segments) => Some(Cow::Owned(with_associated_data(
+                segments.to_owned(),
+                associated_data,
+            ))),
+            None if associated_data.is_empty() => Some(Cow::Borrowed(self.message)),
+            None => None,
+        }
+    }
+
+    fn algorithm_is(&self, name: &str) -> bool {
+        self.header.map_or(true, |header| header.alg == name)
+    }
+
+    pub fn verify(&self, key: &PublicKey) -> bool {
+        self.verify_with_associated_data(key, &[])
+    }
+
+    pub fn verify_hmac(&self, key: &SecretKey) -> bool {
+        self.verify_hmac_with_associated_data(key, &[])
+    }
+
+    /// Same as `SignedMessage::verify_with_associated_data`, allocates for non-empty data
+    pub fn verify_with_associated_data(&self, key: &PublicKey, associated_data: &[u8]) -> bool {
+        self.algorithm_is(key.algorithm().name())
+            && self
+                .signing_input(associated_data)
+                .is_some_and(|input| key.verify(&input, self.signature()))
+    }
+
+    pub fn verify_hmac_with_associated_data(
+        &self,
+        key: &SecretKey,
+        associated_data: &[u8],
+    ) -> bool {
+        self.algorithm_is(key.algorithm_name())
+            && self
+                .signing_input(associated_data)
+                .is_some_and(|input| key.verify(&input, self.signature()))
+    }
+
+    pub fn message(&self) -> &'a [u8] {
+        self.message
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature[..self.signature_len]
+    }
+}
+
+impl Envelope for SignedMessageRef<'_> {
+    fn key_id(&self) -> Option<Cow<'_, str>> {
+        self.key_id().map(Cow::Borrowed)
+    }
+
+    fn verify(&self, key: &PublicKey, associated_data: &[u8]) -> bool {
+        self.verify_with_associated_data(key, associated_data)
+    }
+
+    fn verify_hmac(&self, key: &SecretKey, associated_data: &[u8]) -> bool {
+        self.verify_hmac_with_associated_data(key, associated_data)
+    }
+
+    fn message(&self) -> &[u8] {
+        self.message
+    }
+}
+
+impl fmt::Debug for SignedMessageRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignedMessageRef")
+            .field("header", &self.header)
+            .field("message_len", &self.message.len())
+            .field("signature", &fingerprint(self.signature()))
+            .finish()
+    }
+}
+
+/// Signature of a `MultiSignedMessage` with its header, which always carries the key id
+struct KeySignature {
+    header: EncodedHeader,
+    signature: Vec<u8>,
+}
+
+impl KeySignature {
+    fn key_id(&self) -> &str {
+        self.header.header.kid.as_deref().unwrap_or_default()
+    }
+
+    /// Same input as the one of `SignedMessage`, so every signature stands on its own, the `typ`
+    /// of the header keeps it from verifying as a `SignedMessage`
+    fn signing_input(&self, message: &str, associated_data: &[u8]) -> Vec<u8> {
+        let segments = [self.header.segment.as_str(), message].join(SEPARATOR);
+        with_associated_data(segments, associated_data)
+    }
+}
+
+/// Message signed by several keys, e.g. by two independent issuers or by k of n keys
+///
+/// Encoded as `message~header.signature~header.signature...`. Every signature has its own header
+/// with the algorithm and the id of the signing key, key ids are unique within a message. Headers
+/// are typed `MULTI_SIGNATURE_TYPE`, so a signature can't be passed off as a `SignedMessage`. A
+/// signer can add its signature to a message decoded from another signer.
+pub struct MultiSignedMessage {
+    message: Vec<u8>,
+    signatures: Vec<KeySignature>,
+}
+
+impl MultiSignedMessage {
+    /// Message without signatures, which can't be encoded until it's signed
+    pub fn new(message: Vec<u8>) -> Self {
+        Self {
+            message,
+            signatures: Vec::new(),
         }
     }
+
+    /// Add signature of `signer`, replacing any previous signature of the same `key_id`
+    pub fn sign<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        key_id: impl Into<String>,
+    ) -> Result<(), Error> {
+        self.sign_with_associated_data(signer, key_id, &[])
+    }
+
+    /// Add signature bound to `associated_data`, as `SignedMessage::create_with_associated_data`
+    ///
+    /// Every signature is bound on its own, signers of a message may use different data.
+    pub fn sign_with_associated_data<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        key_id: impl Into<String>,
+        associated_data: &[u8],
+    ) -> Result<(), Error> {
+        let header = Header {
+            version: Some(HEADER_VERSION),
+            alg: signer.algorithm_name().to_owned(),
+            kid: Some(key_id.into()),
+            typ: Some(MULTI_SIGNATURE_TYPE.to_owned()),
+            dig: None,
+            rcp: None,
+        };
+        let segment = b64enc(&serde_json::to_vec(&header).expect("Fail serialize header"));
+        let mut key_signature = KeySignature {
+            header: EncodedHeader { header, segment },
+            signature: Vec::new(),
+        };
+        key_signature.signature =
+            signer.sign(&key_signature.signing_input(&b64enc(&self.message), associated_data))?;
+        self.signatures
+            .retain(|signature| signature.key_id() != key_signature.key_id());
+        self.signatures.push(key_signature);
+        Ok(())
+    }
+
+    pub fn encode(&self) -> String {
+        let signatures = self.signatures.iter().map(|signature| {
+            [
+                signature.header.segment.as_str(),
+                &b64enc(&signature.signature),
+            ]
+            .join(SEPARATOR)
+        });
+        std::iter::once(b64enc(&self.message))
+            .chain(signatures)
+            .collect::<Vec<_>>()
+            .join(SIGNATURE_SEPARATOR)
+    }
+
+    /// Decode message, which is rejected if it has no signature, a signature without key id or
+    /// two signatures of the same key id
+    pub fn decode(s: &str) -> Option<Self> {
+        let mut parts = s.split(SIGNATURE_SEPARATOR);
+        let message = b64dec(parts.next()?)?;
+        let mut signatures: Vec<KeySignature> = Vec::new();
+        for part in parts {
+            let (segment, signature) = part.split_once(SEPARATOR)?;
+            let header = Header::decode(segment).filter(|header| {
+                header.version == Some(HEADER_VERSION)
+                    && header.typ.as_deref() == Some(MULTI_SIGNATURE_TYPE)
+            })?;
+            let key_id = header.kid.as_deref()?;
+            if signatures
+                .iter()
+                .any(|signature| signature.key_id() == key_id)
+            {
+                return None;
+            }
+            let segment = segment.to_owned();
+            signatures.push(KeySignature {
+                header: EncodedHeader { header, segment },
+                signature: b64dec(signature)?,
+            });
+        }
+        if signatures.is_empty() {
+            return None;
+        }
+        Some(Self {
+            message,
+            signatures,
+        })
+    }
+
+    /// Ids of the keys which signed the message
+    pub fn key_ids(&self) -> impl Iterator<Item = &str> {
+        self.signatures.iter().map(KeySignature::key_id)
+    }
+
+    /// Verify signature of `key_id`, `false` if there's none
+    pub fn verify(&self, key_id: &str, key: &PublicKey) -> bool {
+        self.verify_with_associated_data(key_id, key, &[])
+    }
+
+    /// Verify signature of `key_id` bound to `associated_data`
+    pub fn verify_with_associated_data(
+        &self,
+        key_id: &str,
+        key: &PublicKey,
+        associated_data: &[u8],
+    ) -> bool {
+        let message = b64enc(&self.message);
+        self.signatures
+            .iter()
+            .find(|signature| signature.key_id() == key_id)
+            .is_some_and(|signature| {
+                signature.header.header.alg == key.algorithm().name()
+                    && key.verify(
+                        &signature.signing_input(&message, associated_data),
+                        &signature.signature,
+                    )
+            })
+    }
+
+    /// Verify signature tagged by `key` in symmetric mode, whatever its key id
+    pub fn verify_hmac(&self, key: &SecretKey) -> bool {
+        self.verify_hmac_with_associated_data(key, &[])
+    }
+
+    pub fn verify_hmac_with_associated_data(
+        &self,
+        key: &SecretKey,
+        associated_data: &[u8],
+    ) -> bool {
+        let message = b64enc(&self.message);
+        self.signatures.iter().any(|signature| {
+            signature.header.header.alg == key.algorithm_name()
+                && key.verify(
+                    &signature.signing_input(&message, associated_data),
+                    &signature.signature,
+                )
+        })
+    }
+
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+}
+
+impl fmt::Debug for MultiSignedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiSignedMessage")
+            .field("key_ids", &self.key_ids().collect::<Vec<_>>())
+            .field("message_len", &self.message.len())
+            .finish()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::crypto::tests::{get_test_private_key, get_test_public_key};
+    use crate::crypto::tests::{
+        ecdsa_test_keys, get_test_private_key, get_test_public_key, get_test_secret_key, MockSigner,
+    };
+    use crate::crypto::PrivateKey;
+    use crate::error::Error::SigningFail;
 
     use super::*;
 
     #[test]
     fn serialization() {
         let sm1 = SignedMessage {
+            header: None,
             message: "message".as_bytes().to_vec(),
             signature: "signature".as_bytes().to_vec(),
         };
         let sm2 = SignedMessage::decode(&sm1.encode()).unwrap();
         assert_eq!(sm1.message, sm2.message);
         assert_eq!(sm1.signature, sm2.signature);
+        assert!(sm2.header().is_none());
+    }
+
+    #[test]
+    fn serialization_with_key_id() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let sm1 =
+            SignedMessage::create_with_key_id("message".as_bytes().to_vec(), &key, "k1").unwrap();
+        let encoded = sm1.encode();
+        assert_eq!(encoded.split(SEPARATOR).count(), 3);
+
+        let sm2 = SignedMessage::decode(&encoded).unwrap();
+        assert_eq!(sm2.key_id(), Some("k1"));
+        assert_eq!(
+            sm2.header(),
+            Some(&Header {
+                version: Some(HEADER_VERSION),
+                alg: "EdDSA".to_owned(),
+                kid: Some("k1".to_owned()),
+                typ: None,
+                dig: None,
+                rcp: None,
+            })
+        );
+        assert_eq!(sm1.message, sm2.message);
+        assert_eq!(sm1.signature, sm2.signature);
+
+        let debug = format!("{:?}", sm2);
+        assert!(debug.contains("k1"));
+        assert!(encoded.split(SEPARATOR).skip(1).all(|s| !debug.contains(s)));
+
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        assert!(sm2.verify(&public_key));
+    }
+
+    #[test]
+    fn hmac_mode() {
+        let secret_key = SecretKey::from_base64(&get_test_secret_key()).unwrap();
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+
+        let sm = SignedMessage::decode(
+            &SignedMessage::create("message".as_bytes().to_vec(), &secret_key)
+                .unwrap()
+                .encode(),
+        )
+        .unwrap();
+        assert!(sm.verify_hmac(&secret_key));
+        assert!(!sm.verify(&public_key));
+
+        let sm = SignedMessage::create("message".as_bytes().to_vec(), &private_key).unwrap();
+        assert!(!sm.verify_hmac(&secret_key));
+    }
+
+    #[test]
+    fn external_signer() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let signer: Box<dyn Signer> = Box::new(MockSigner::new(key));
+        let sm = SignedMessage::create("message".as_bytes().to_vec(), signer.as_ref()).unwrap();
+        assert!(sm.verify(&public_key));
+
+        let sm = SignedMessage::create("message".as_bytes().to_vec(), &MockSigner::unavailable());
+        assert!(matches!(sm, Err(SigningFail)));
+    }
+
+    #[test]
+    fn decode_should_reject_bad_segments() {
+        assert!(SignedMessage::decode("bWVzc2FnZQ").is_none());
+        assert!(SignedMessage::decode("a2V5.bWVzc2FnZQ.c2ln.ZXh0cmE").is_none());
+        assert!(SignedMessage::decode("_w.bWVzc2FnZQ.c2ln").is_none());
+
+        let with_header = |header: &str| format!("{}.bWVzc2FnZQ.c2ln", b64enc(header.as_bytes()));
+        assert!(SignedMessage::decode(&with_header(r#"{"v":1,"alg":"EdDSA"}"#)).is_some());
+        // unknown version
+        assert!(SignedMessage::decode(&with_header(r#"{"v":2,"alg":"EdDSA"}"#)).is_none());
+        // unknown field
+        let header = r#"{"v":1,"alg":"EdDSA","crit":["exp"]}"#;
+        assert!(SignedMessage::decode(&with_header(header)).is_none());
+        let header = r#"{"v":1,"alg":"EdDSA","alg":"none"}"#;
+        assert!(SignedMessage::decode(&with_header(header)).is_none());
+        let header = r#"{"v":null,"alg":"EdDSA"}"#;
+        assert!(SignedMessage::decode(&with_header(header)).is_none());
+
+        // JWS ignores unknown parameters, except critical ones
+        let header = r#"{"alg":"EdDSA","x5t":"dGVzdA"}"#;
+        assert!(SignedMessage::decode(&with_header(header)).is_some());
+        let header = r#"{"alg":"EdDSA","crit":["exp"],"exp":1}"#;
+        assert!(SignedMessage::decode(&with_header(header)).is_none());
+        assert!(SignedMessage::decode(&with_header(r#"{"kid":"k1"}"#)).is_none());
+    }
+
+    #[test]
+    fn jws() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let claims = br#"{"sub":"user"}"#.to_vec();
+        let encoded = SignedMessage::create_jws(claims.clone(), &key, Some("k1".to_owned()))
+            .unwrap()
+            .encode();
+        let header = encoded.split(SEPARATOR).next().unwrap();
+        assert_eq!(
+            b64dec(header).unwrap(),
+            br#"{"alg":"EdDSA","kid":"k1","typ":"JWT"}"#.to_vec()
+        );
+
+        let sm = SignedMessage::decode(&encoded).unwrap();
+        assert_eq!(sm.header().unwrap().version, None);
+        assert_eq!(sm.key_id(), Some("k1"));
+        assert_eq!(sm.message(), claims.as_slice());
+        assert!(sm.verify(&public_key));
+    }
+
+    #[test]
+    fn should_verify_jws_of_other_issuers() {
+        // RFC 8037 appendix A.4
+        let jws = "eyJhbGciOiJFZERTQSJ9.RXhhbXBsZSBvZiBFZDI1NTE5IHNpZ25pbmc.hgyY0il_MGCjP0JzlnLWG1PPOt7-09PGcvMg3AIbQR6dWbhijcNR4ki4iylGjg5BhVsPt9g7sVvpAr_MuM0KAg";
+        let public_key =
+            PublicKey::from_base64("11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo").unwrap();
+        let sm = SignedMessage::decode(jws).unwrap();
+        assert_eq!(sm.message(), b"Example of Ed25519 signing");
+        assert!(sm.verify(&public_key));
+
+        // unsecured JWS is never accepted
+        let unsecured = format!(
+            "{}.{}",
+            b64enc(br#"{"alg":"none"}"#),
+            jws.split_once(SEPARATOR).unwrap().1
+        );
+        assert!(!SignedMessage::decode(&unsecured)
+            .unwrap()
+            .verify(&public_key));
+    }
+
+    #[test]
+    fn header_should_be_signed() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let sm = SignedMessage::create_with_header(
+            "message".as_bytes().to_vec(),
+            &key,
+            Some("k1".to_owned()),
+            Some("access".to_owned()),
+        )
+        .unwrap();
+        assert!(sm.verify(&public_key));
+        assert_eq!(sm.header().unwrap().typ.as_deref(), Some("access"));
+
+        // header replaced by the one of another message
+        let other = SignedMessage::create_with_key_id("message".as_bytes().to_vec(), &key, "k2")
+            .unwrap()
+            .encode();
+        let encoded = sm.encode();
+        let tampered = [
+            other.split(SEPARATOR).next().unwrap(),
+            encoded.split_once(SEPARATOR).unwrap().1,
+        ]
+        .join(SEPARATOR);
+        assert!(!SignedMessage::decode(&tampered)
+            .unwrap()
+            .verify(&public_key));
+
+        // header claims algorithm other than the one of the key
+        let header = Header {
+            version: Some(HEADER_VERSION),
+            alg: "ES256".to_owned(),
+            kid: None,
+            typ: None,
+            dig: None,
+            rcp: None,
+        };
+        let segment = b64enc(&serde_json::to_vec(&header).unwrap());
+        let mut sm = SignedMessage {
+            header: Some(EncodedHeader { header, segment }),
+            message: "message".as_bytes().to_vec(),
+            signature: Vec::new(),
+        };
+        sm.signature = key.sign(&sm.signing_input(&[]).unwrap()).unwrap();
+        assert!(!sm.verify(&public_key));
     }
 
     #[test]
     fn create_should_return_predicable_result() {
         let message = "message".as_bytes().to_vec();
         let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
-        let sm = SignedMessage::create(message, &key);
-        assert_eq!(sm.encode(), String::from("bWVzc2FnZQ.gH3fe9YO9tEv7f8adiZ2w7F6-7doNp3yyaDrfWuQNCuJi6bwF2jqm7v4p-wANdOahO1wvULOH96JJDnQlUoEDw"));
+        let sm = SignedMessage::create(message, &key).unwrap();
+        assert_eq!(sm.encode(), String::from("eyJ2IjoxLCJhbGciOiJFZERTQSJ9.bWVzc2FnZQ.x87Dyld9BrnzrQQ02GdvB26YRdK-MInuNFtJ8z5YCsI02Jssg8QZrdAVI-DivbDSD297QBzzP2w32vnIqRugCg"));
     }
 
     #[test]
@@ -77,4 +1198,314 @@ mod tests {
         let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
         assert!(sm.verify(&public_key));
     }
+
+    #[test]
+    fn multi_signed_message() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let (ecdsa_key, ecdsa_public_key) = ecdsa_test_keys();
+
+        let mut sm = MultiSignedMessage::new("message".as_bytes().to_vec());
+        sm.sign(&key, "ed").unwrap();
+        // second issuer signs the message it received
+        let mut sm = MultiSignedMessage::decode(&sm.encode()).unwrap();
+        sm.sign(&ecdsa_key, "ec").unwrap();
+        sm.sign(&key, "ed").unwrap();
+
+        let encoded = sm.encode();
+        assert_eq!(encoded.split(SIGNATURE_SEPARATOR).count(), 3);
+        let sm = MultiSignedMessage::decode(&encoded).unwrap();
+        assert_eq!(sm.message(), b"message");
+        assert_eq!(sm.key_ids().collect::<Vec<_>>(), vec!["ec", "ed"]);
+        assert!(sm.verify("ed", &public_key));
+        assert!(sm.verify("ec", &ecdsa_public_key));
+        assert!(!sm.verify("ec", &public_key));
+        assert!(!sm.verify("other", &public_key));
+        assert!(!format!("{:?}", sm).contains(encoded.split(SEPARATOR).last().unwrap()));
+
+        // every signature covers the message
+        let other = MultiSignedMessage::decode(&encoded.replacen("bWVzc2FnZQ", "b3RoZXI", 1));
+        assert!(!other.unwrap().verify("ed", &public_key));
+    }
+
+    #[test]
+    fn multi_signed_message_hmac() {
+        let secret_key = SecretKey::from_base64(&get_test_secret_key()).unwrap();
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let mut sm = MultiSignedMessage::new("message".as_bytes().to_vec());
+        sm.sign(&key, "ed").unwrap();
+        assert!(!sm.verify_hmac(&secret_key));
+        sm.sign(&secret_key, "hs").unwrap();
+        assert!(sm.verify_hmac(&secret_key));
+    }
+
+    #[test]
+    fn associated_data() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let secret_key = SecretKey::from_base64(&get_test_secret_key()).unwrap();
+        let message = "message".as_bytes().to_vec();
+
+        let sm = SignedMessage::create_with_associated_data(
+            message.clone(),
+            &key,
+            Some("k1".to_owned()),
+            b"api.example.com",
+        )
+        .unwrap();
+        let encoded = sm.encode();
+        // associated data isn't transmitted
+        assert!(!encoded.contains(&b64enc(b"api.example.com")));
+        let sm = SignedMessage::decode(&encoded).unwrap();
+        assert!(sm.verify_with_associated_data(&public_key, b"api.example.com"));
+        assert!(!sm.verify_with_associated_data(&public_key, b"other.example.com"));
+        assert!(!sm.verify(&public_key));
+
+        let mut buffer = vec![0; encoded.len()];
+        let sm_ref = SignedMessageRef::decode(&encoded, &mut buffer).unwrap();
+        assert!(sm_ref.verify_with_associated_data(&public_key, b"api.example.com"));
+        assert!(!sm_ref.verify_with_associated_data(&public_key, b"other.example.com"));
+        assert!(!sm_ref.verify(&public_key));
+
+        // token without associated data isn't valid in a context
+        let sm = SignedMessage::create(message.clone(), &key).unwrap();
+        assert!(sm.verify_with_associated_data(&public_key, &[]));
+        assert!(!sm.verify_with_associated_data(&public_key, b"api.example.com"));
+        let legacy = SignedMessage {
+            header: None,
+            message: message.clone(),
+            signature: key.sign(&message).unwrap(),
+        };
+        assert!(legacy.verify(&public_key));
+        assert!(!legacy.verify_with_associated_data(&public_key, b"api.example.com"));
+
+        let sm =
+            SignedMessage::create_with_associated_data(message.clone(), &secret_key, None, b"ad")
+                .unwrap();
+        assert!(sm.verify_hmac_with_associated_data(&secret_key, b"ad"));
+        assert!(!sm.verify_hmac(&secret_key));
+
+        let mut msm = MultiSignedMessage::new(message);
+        msm.sign_with_associated_data(&key, "ed", b"ad").unwrap();
+        msm.sign_with_associated_data(&secret_key, "hs", b"ad")
+            .unwrap();
+        let msm = MultiSignedMessage::decode(&msm.encode()).unwrap();
+        assert!(msm.verify_with_associated_data("ed", &public_key, b"ad"));
+        assert!(!msm.verify("ed", &public_key));
+        assert!(msm.verify_hmac_with_associated_data(&secret_key, b"ad"));
+        assert!(!msm.verify_hmac(&secret_key));
+    }
+
+    #[test]
+    fn multi_signed_message_should_reject_malformed() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let mut sm = MultiSignedMessage::new("message".as_bytes().to_vec());
+        sm.sign(&key, "k1").unwrap();
+        let encoded = sm.encode();
+        let signature = encoded.split_once(SIGNATURE_SEPARATOR).unwrap().1;
+        assert!(MultiSignedMessage::decode(&encoded).is_some());
+
+        // no signature
+        assert!(MultiSignedMessage::decode("bWVzc2FnZQ").is_none());
+        assert!(MultiSignedMessage::decode("bWVzc2FnZQ~").is_none());
+        // duplicated key id
+        let duplicated = [encoded.as_str(), signature].join(SIGNATURE_SEPARATOR);
+        assert!(MultiSignedMessage::decode(&duplicated).is_none());
+        // signature without key id, version or type
+        for header in [
+            r#"{"v":1,"alg":"EdDSA","typ":"multi"}"#,
+            r#"{"alg":"EdDSA","kid":"k2","typ":"multi"}"#,
+            r#"{"v":1,"alg":"EdDSA","kid":"k2"}"#,
+        ]
+        .iter()
+        {
+            let part = format!("{}.c2ln", b64enc(header.as_bytes()));
+            let s = [encoded.as_str(), &part].join(SIGNATURE_SEPARATOR);
+            assert!(MultiSignedMessage::decode(&s).is_none());
+        }
+        // single signed message
+        let single = SignedMessage::create(b"message".to_vec(), &key).unwrap();
+        assert!(MultiSignedMessage::decode(&single.encode()).is_none());
+    }
+
+    #[test]
+    fn multi_signature_should_not_verify_alone() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let mut sm = MultiSignedMessage::new("message".as_bytes().to_vec());
+        sm.sign(&key, "k1").unwrap();
+        let encoded = sm.encode();
+        let (message, signature) = encoded.split_once(SIGNATURE_SEPARATOR).unwrap();
+        let (header, signature) = signature.split_once(SEPARATOR).unwrap();
+
+        // header.message.signature, which the co-signature covers
+        let lifted = [header, message, signature].join(SEPARATOR);
+        assert!(SignedMessage::decode(&lifted).is_none());
+        let mut buffer = vec![0; lifted.len()];
+        assert!(SignedMessageRef::decode(&lifted, &mut buffer).is_none());
+
+        // what the typ keeps from verifying
+        let sm = SignedMessage {
+            header: Some(EncodedHeader {
+                header: Header::decode(header).unwrap(),
+                segment: header.to_owned(),
+            }),
+            message: b"message".to_vec(),
+            signature: b64dec(signature).unwrap(),
+        };
+        assert!(sm.verify(&public_key));
+    }
+
+    #[test]
+    fn borrowed_decode() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let secret_key = SecretKey::from_base64(&get_test_secret_key()).unwrap();
+        let (ecdsa_key, ecdsa_public_key) = ecdsa_test_keys();
+        let message = "message".as_bytes().to_vec();
+
+        let encoded = SignedMessage::create_with_header(
+            message.clone(),
+            &key,
+            Some("k1".to_owned()),
+            Some("access".to_owned()),
+        )
+        .unwrap()
+        .encode();
+        let mut buffer = [0u8; 256];
+        let sm = SignedMessageRef::decode(&encoded, &mut buffer).unwrap();
+        assert_eq!(
+            sm.header(),
+            Some(&HeaderRef {
+                version: Some(HEADER_VERSION),
+                alg: "EdDSA",
+                kid: Some("k1"),
+                typ: Some("access"),
+            })
+        );
+        assert_eq!(sm.message(), message.as_slice());
+        assert!(sm.verify(&public_key));
+        assert!(!sm.verify(&ecdsa_public_key));
+        assert!(!sm.verify_hmac(&secret_key));
+
+        let legacy = "bWVzc2FnZQ.gH3fe9YO9tEv7f8adiZ2w7F6-7doNp3yyaDrfWuQNCuJi6bwF2jqm7v4p-wANdOahO1wvULOH96JJDnQlUoEDw";
+        let mut buffer = vec![0u8; legacy.len()];
+        let sm = SignedMessageRef::decode(legacy, &mut buffer).unwrap();
+        assert!(sm.header().is_none());
+        assert!(sm.verify(&public_key));
+
+        for encoded in [
+            SignedMessage::create(message.clone(), &secret_key).unwrap(),
+            SignedMessage::create(message.clone(), &ecdsa_key).unwrap(),
+            SignedMessage::create_jws(message.clone(), &key, None).unwrap(),
+        ]
+        .iter()
+        .map(SignedMessage::encode)
+        {
+            // buffer as long as the message is always enough
+            let mut buffer = vec![0u8; encoded.len()];
+            let sm = SignedMessageRef::decode(&encoded, &mut buffer).unwrap();
+            let owned = SignedMessage::decode(&encoded).unwrap();
+            assert_eq!(sm.message(), owned.message());
+            assert_eq!(sm.signature(), owned.signature());
+            assert_eq!(sm.key_id(), owned.key_id());
+            assert!(
+                sm.verify(&public_key)
+                    || sm.verify(&ecdsa_public_key)
+                    || sm.verify_hmac(&secret_key)
+            );
+        }
+    }
+
+    #[test]
+    fn borrowed_decode_should_reject_what_owned_rejects() {
+        let with_header = |header: &str| format!("{}.bWVzc2FnZQ.c2ln", b64enc(header.as_bytes()));
+        let cases = [
+            "bWVzc2FnZQ".to_owned(),
+            "a2V5.bWVzc2FnZQ.c2ln.ZXh0cmE".to_owned(),
+            "_w.bWVzc2FnZQ.c2ln".to_owned(),
+            with_header(r#"{"v":1,"alg":"EdDSA"}"#),
+            with_header(r#"{"v":2,"alg":"EdDSA"}"#),
+            with_header(r#"{"v":1,"alg":"EdDSA","crit":["exp"]}"#),
+            with_header(r#"{"v":1,"alg":"EdDSA","alg":"none"}"#),
+            with_header(r#"{"v":null,"alg":"EdDSA"}"#),
+            with_header(r#"{"alg":"EdDSA","x5t":"dGVzdA"}"#),
+            with_header(r#"{"alg":"EdDSA","crit":["exp"],"exp":1}"#),
+            with_header(r#"{"kid":"k1"}"#),
+        ];
+        for case in cases.iter() {
+            let mut buffer = [0u8; 128];
+            assert_eq!(
+                SignedMessageRef::decode(case, &mut buffer).is_some(),
+                SignedMessage::decode(case).is_some(),
+                "{}",
+                case
+            );
+        }
+
+        // too small buffer and too long signature
+        let mut buffer = [0u8; 4];
+        assert!(SignedMessageRef::decode(&cases[3], &mut buffer).is_none());
+        let mut buffer = [0u8; 256];
+        let long_signature = format!("bWVzc2FnZQ.{}", b64enc(&[0u8; MAX_SIGNATURE_LEN + 1]));
+        assert!(SignedMessageRef::decode(&long_signature, &mut buffer).is_none());
+    }
+
+    #[test]
+    fn detached() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let secret_key = SecretKey::from_base64(&get_test_secret_key()).unwrap();
+        let body = vec![7u8; 100_000];
+        let payload = || {
+            let mut payload = DetachedPayload::new();
+            body.chunks(4096).for_each(|chunk| payload.update(chunk));
+            payload
+        };
+
+        let encoded = SignedMessage::create_detached(payload(), &key, Some("k1".to_owned()))
+            .unwrap()
+            .encode();
+        assert!(encoded.contains(".."));
+        let sm = SignedMessage::decode(&encoded).unwrap();
+        assert!(sm.is_detached());
+        assert_eq!(sm.key_id(), Some("k1"));
+        assert!(sm.message().is_empty());
+        assert!(sm.verify_detached(payload(), &public_key));
+        assert!(sm.verify_detached(
+            DetachedPayload::from_reader(&body[..]).unwrap(),
+            &public_key
+        ));
+        // never verified as a message with embedded payload
+        assert!(!sm.verify(&public_key));
+
+        let mut other = payload();
+        other.update(b"appended");
+        assert!(!sm.verify_detached(other, &public_key));
+
+        let sm = SignedMessage::create_detached(payload(), &secret_key, None).unwrap();
+        assert!(sm.verify_detached_hmac(payload(), &secret_key));
+        assert!(!sm.verify_hmac(&secret_key));
+
+        // embedded message isn't verified as detached
+        let sm = SignedMessage::create(b"message".to_vec(), &key).unwrap();
+        let mut message = DetachedPayload::new();
+        message.update(b"message");
+        assert!(!sm.verify_detached(message, &public_key));
+    }
+
+    #[test]
+    fn detached_should_reject_embedded_payload() {
+        let key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let encoded = SignedMessage::create_detached(DetachedPayload::new(), &key, None)
+            .unwrap()
+            .encode();
+        let (header, signature) = encoded.split_once("..").unwrap();
+        let embedded = [header, "bWVzc2FnZQ", signature].join(SEPARATOR);
+        assert!(SignedMessage::decode(&embedded).is_none());
+
+        let header = r#"{"v":1,"alg":"EdDSA","dig":"S512"}"#;
+        let other_digest = format!("{}..c2ln", b64enc(header.as_bytes()));
+        assert!(SignedMessage::decode(&other_digest).is_none());
+    }
 }
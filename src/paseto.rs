@@ -0,0 +1,247 @@
+//! PASETO `v4.public` tokens, an alternative wire format to `SignedMessage`
+//!
+//! A token is `v4.public.` followed by the base64url encoded message and Ed25519 signature, and
+//! an optional base64url encoded footer. The signature covers the pre-authentication encoding
+//! (PAE) of the header, message, footer and implicit assertion. The implicit assertion is never
+//! part of the token, issuer and validator have to agree on it.
+
+use std::borrow::Cow;
+
+use serde::Deserialize;
+
+use crate::crypto::{Algorithm, PublicKey, SecretKey, Signer};
+use crate::encoding::{b64dec_strict, b64enc};
+use crate::error::Error::{self, *};
+use crate::message::Envelope;
+
+pub const V4_PUBLIC_HEADER: &str = "v4.public.";
+const SEPARATOR: &str = ".";
+pub(crate) const SIGNATURE_LEN: usize = 64;
+
+/// Footer fields which are understood, any other footer is opaque
+#[derive(Deserialize)]
+struct Footer {
+    kid: String,
+}
+
+/// PASETO `v4.public` token
+pub struct PasetoMessage {
+    message: Vec<u8>,
+    footer: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+/// Pre-authentication encoding, every piece prefixed by its length so pieces can't be shifted
+fn pae(pieces: &[&[u8]]) -> Vec<u8> {
+    // most significant bit is cleared for interoperability with signed 64 bits integers
+    fn le64(n: usize) -> [u8; 8] {
+        (n as u64 & (u64::MAX >> 1)).to_le_bytes()
+    }
+    let len = 8 + pieces.iter().map(|piece| 8 + piece.len()).sum::<usize>();
+    let mut output = Vec::with_capacity(len);
+    output.extend_from_slice(&le64(pieces.len()));
+    for piece in pieces {
+        output.extend_from_slice(&le64(piece.len()));
+        output.extend_from_slice(piece);
+    }
+    output
+}
+
+impl PasetoMessage {
+    /// Sign `message` with an optional `footer`, which is signed but not encrypted, and an
+    /// `implicit` assertion, which is signed but not stored in the token
+    ///
+    /// `v4.public` is bound to Ed25519, a signer of any other algorithm is rejected by
+    /// `Error::UnsupportedKeyAlgorithm`.
+    pub fn create<S: Signer + ?Sized>(
+        message: Vec<u8>,
+        footer: Vec<u8>,
+        implicit: &[u8],
+        signer: &S,
+    ) -> Result<Self, Error> {
+        if signer.algorithm_name() != Algorithm::Ed25519.name() {
+            return Err(UnsupportedKeyAlgorithm);
+        }
+        let signature = signer.sign(&Self::signing_input(&message, &footer, implicit))?;
+        Ok(Self {
+            message,
+            footer,
+            signature,
+        })
+    }
+
+    fn signing_input(message: &[u8], footer: &[u8], implicit: &[u8]) -> Vec<u8> {
+        pae(&[V4_PUBLIC_HEADER.as_bytes(), message, footer, implicit])
+    }
+
+    pub fn encode(&self) -> String {
+        let body = b64enc(&[self.message.as_slice(), &self.signature].concat());
+        if self.footer.is_empty() {
+            [V4_PUBLIC_HEADER, &body].concat()
+        } else {
+            [V4_PUBLIC_HEADER, &body, SEPARATOR, &b64enc(&self.footer)].concat()
+        }
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let rest = s.strip_prefix(V4_PUBLIC_HEADER)?;
+        let (body, footer) = match rest.split_once(SEPARATOR) {
+            Some((_, footer)) if footer.is_empty() || footer.contains(SEPARATOR) => return None,
+            Some((body, footer)) => (body, b64dec_strict(footer)?),
+            None => (rest, Vec::new()),
+        };
+        let mut message = b64dec_strict(body)?;
+        if message.len() < SIGNATURE_LEN {
+            return None;
+        }
+        let signature = message.split_off(message.len() - SIGNATURE_LEN);
+        Some(Self {
+            message,
+            footer,
+            signature,
+        })
+    }
+
+    /// Verify token signed with the same `implicit` assertion, only Ed25519 keys verify a token
+    pub fn verify(&self, key: &PublicKey, implicit: &[u8]) -> bool {
+        key.algorithm() == Algorithm::Ed25519
+            && key.verify(
+                &Self::signing_input(&self.message, &self.footer, implicit),
+                &self.signature,
+            )
+    }
+
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    pub fn footer(&self) -> &[u8] {
+        &self.footer
+    }
+
+    /// `kid` of a JSON footer such as `{"kid":"k1"}`
+    pub fn key_id(&self) -> Option<String> {
+        serde_json::from_slice::<Footer>(&self.footer)
+            .ok()
+            .map(|footer| footer.kid)
+    }
+}
+
+/// Associated data is the implicit assertion
+impl Envelope for PasetoMessage {
+    fn key_id(&self) -> Option<Cow<'_, str>> {
+        self.key_id().map(Cow::Owned)
+    }
+
+    fn verify(&self, key: &PublicKey, associated_data: &[u8]) -> bool {
+        self.verify(key, associated_data)
+    }
+
+    /// Secret keys are for `v4.local` tokens, which are never accepted
+    fn verify_hmac(&self, _key: &SecretKey, _associated_data: &[u8]) -> bool {
+        false
+    }
+
+    fn message(&self) -> &[u8] {
+        &self.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::tests::{ecdsa_test_keys, get_test_private_key, get_test_public_key, hex};
+    use crate::crypto::PrivateKey;
+
+    use super::*;
+
+    // official test vectors, https://github.com/paseto-standard/test-vectors/blob/master/v4.json
+    const SECRET_KEY: &str = "b4cbfb43df4ce210727d953e4a713307fa19bb7d9f85041438d9e11b942a3774";
+    const PUBLIC_KEY: &str = "1eb9dbbbbc047c03fd70604e0071f0987e16b28b757225c11f00415d0e20b1a2";
+    const PAYLOAD: &str =
+        r#"{"data":"this is a signed message","exp":"2022-01-01T00:00:00+00:00"}"#;
+    const FOOTER: &str = r#"{"kid":"zVhMiPBP9fRf2snEcT7gFTioeA9COcNy9DfgL1W60haN"}"#;
+    const IMPLICIT: &str = r#"{"test-vector":"4-S-3"}"#;
+    const TOKEN_4_S_1: &str = "v4.public.eyJkYXRhIjoidGhpcyBpcyBhIHNpZ25lZCBtZXNzYWdlIiwiZXhwIjoiMjAyMi0wMS0wMVQwMDowMDowMCswMDowMCJ9bg_XBBzds8lTZShVlwwKSgeKpLT3yukTw6JUz3W4h_ExsQV-P0V54zemZDcAxFaSeef1QlXEFtkqxT1ciiQEDA";
+    const TOKEN_4_S_2: &str = "v4.public.eyJkYXRhIjoidGhpcyBpcyBhIHNpZ25lZCBtZXNzYWdlIiwiZXhwIjoiMjAyMi0wMS0wMVQwMDowMDowMCswMDowMCJ9v3Jt8mx_TdM2ceTGoqwrh4yDFn0XsHvvV_D0DtwQxVrJEBMl0F2caAdgnpKlt4p7xBnx1HcO-SPo8FPp214HDw.eyJraWQiOiJ6VmhNaVBCUDlmUmYyc25FY1Q3Z0ZUaW9lQTlDT2NOeTlEZmdMMVc2MGhhTiJ9";
+    const TOKEN_4_S_3: &str = "v4.public.eyJkYXRhIjoidGhpcyBpcyBhIHNpZ25lZCBtZXNzYWdlIiwiZXhwIjoiMjAyMi0wMS0wMVQwMDowMDowMCswMDowMCJ9NPWciuD3d0o5eXJXG5pJy-DiVEoyPYWs1YSTwWHNJq6DZD3je5gf-0M4JR9ipdUSJbIovzmBECeaWmaqcaP0DQ.eyJraWQiOiJ6VmhNaVBCUDlmUmYyc25FY1Q3Z0ZUaW9lQTlDT2NOeTlEZmdMMVc2MGhhTiJ9";
+
+    #[test]
+    fn pae_spec_examples() {
+        assert_eq!(pae(&[]), vec![0; 8]);
+        assert_eq!(
+            pae(&[b""]),
+            [&[1u8, 0, 0, 0, 0, 0, 0, 0][..], &[0; 8]].concat()
+        );
+        assert_eq!(
+            pae(&[b"test"]),
+            [
+                &[1u8, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0][..],
+                b"test"
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_vectors() {
+        let private_key = PrivateKey::from_bytes(&hex(SECRET_KEY)).unwrap();
+        let public_key = PublicKey::from_bytes(&hex(PUBLIC_KEY)).unwrap();
+        assert_eq!(private_key.public_key().as_bytes(), public_key.as_bytes());
+
+        let vectors = [
+            (TOKEN_4_S_1, "", ""),
+            (TOKEN_4_S_2, FOOTER, ""),
+            (TOKEN_4_S_3, FOOTER, IMPLICIT),
+        ];
+        for (token, footer, implicit) in vectors.iter() {
+            let message = PasetoMessage::create(
+                PAYLOAD.as_bytes().to_vec(),
+                footer.as_bytes().to_vec(),
+                implicit.as_bytes(),
+                &private_key,
+            )
+            .unwrap();
+            assert_eq!(message.encode(), *token);
+
+            let decoded = PasetoMessage::decode(token).unwrap();
+            assert_eq!(decoded.message(), PAYLOAD.as_bytes());
+            assert_eq!(decoded.footer(), footer.as_bytes());
+            assert!(decoded.verify(&public_key, implicit.as_bytes()));
+            assert!(!decoded.verify(&public_key, b"other"));
+        }
+    }
+
+    #[test]
+    fn key_id() {
+        let token = PasetoMessage::decode(TOKEN_4_S_2).unwrap();
+        assert_eq!(
+            token.key_id().as_deref(),
+            Some("zVhMiPBP9fRf2snEcT7gFTioeA9COcNy9DfgL1W60haN")
+        );
+        assert_eq!(PasetoMessage::decode(TOKEN_4_S_1).unwrap().key_id(), None);
+    }
+
+    #[test]
+    fn decode_should_reject_malformed() {
+        assert!(PasetoMessage::decode(&TOKEN_4_S_1.replace("v4.public", "v4.local")).is_none());
+        assert!(PasetoMessage::decode(&TOKEN_4_S_1.replace("v4.public", "v3.public")).is_none());
+        assert!(PasetoMessage::decode(&format!("{}=", TOKEN_4_S_1)).is_none());
+        assert!(PasetoMessage::decode(&format!("{}.", TOKEN_4_S_1)).is_none());
+        assert!(PasetoMessage::decode(&format!("{}.e30.e30", TOKEN_4_S_1)).is_none());
+        // shorter than signature
+        assert!(PasetoMessage::decode("v4.public.AAAA").is_none());
+    }
+
+    #[test]
+    fn should_reject_other_algorithms() {
+        let (ecdsa_private_key, ecdsa_public_key) = ecdsa_test_keys();
+        let result = PasetoMessage::create(b"message".to_vec(), vec![], &[], &ecdsa_private_key);
+        assert!(matches!(result, Err(UnsupportedKeyAlgorithm)));
+
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let public_key = PublicKey::from_base64(&get_test_public_key()).unwrap();
+        let token = PasetoMessage::create(b"message".to_vec(), vec![], &[], &private_key).unwrap();
+        assert!(token.verify(&public_key, &[]));
+        assert!(!token.verify(&ecdsa_public_key, &[]));
+    }
+}
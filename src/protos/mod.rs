@@ -1,3 +1,10 @@
 pub use token::*;
 
+// lints of newer toolchains which the generated code trips
+#[allow(
+    unknown_lints,
+    renamed_and_removed_lints,
+    unused_parens,
+    mismatched_lifetime_syntaxes
+)]
 mod token;
@@ -1,7 +1,7 @@
 use num_traits::FromPrimitive;
 
 pub use policy_cond::PolicyCond;
-pub use policy_set::PolicySet;
+pub use policy_set::{PolicySet, PolicySetRef};
 pub use role_set::RoleSet;
 pub use traits::{Policy, Role};
 
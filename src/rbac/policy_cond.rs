@@ -3,7 +3,7 @@ use std::iter::FromIterator;
 use PolicyCond::*;
 
 use crate::rbac::Policy;
-use crate::rbac::PolicySet;
+use crate::rbac::{PolicySet, PolicySetRef};
 
 #[derive(Clone)]
 pub enum PolicyCond<P: Policy> {
@@ -15,11 +15,20 @@ pub enum PolicyCond<P: Policy> {
 
 impl<P: Policy> PolicyCond<P> {
     pub fn satisfy(&self, policies: &PolicySet<P>) -> bool {
+        self.satisfy_by(|policy| policies.contains(policy))
+    }
+
+    /// Same as `satisfy` for policies of a borrowed access token
+    pub fn satisfy_ref(&self, policies: &PolicySetRef<P>) -> bool {
+        self.satisfy_by(|policy| policies.contains(policy))
+    }
+
+    fn satisfy_by(&self, contains: impl Fn(&P) -> bool) -> bool {
         match self {
             NoCheck => true,
-            Contains(policy) => policies.contains(policy),
-            Any(set) => set.iter().any(|p| policies.contains(p)),
-            All(set) => !set.is_empty() && set.iter().all(|p| policies.contains(p)),
+            Contains(policy) => contains(policy),
+            Any(set) => set.iter().any(&contains),
+            All(set) => !set.is_empty() && set.iter().all(&contains),
         }
     }
 
@@ -1,5 +1,6 @@
 use std::collections::BTreeSet;
 use std::iter::FromIterator;
+use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 
 use bitvec::prelude::*;
@@ -59,6 +60,9 @@ impl<P: Policy> PolicySet<P> {
     /// Identity server may use a newer version of policy library which likely to add newer policies.
     /// If that policies are used on outdated web server, this function will return error result
     /// with known policies.
+    ///
+    /// Every bit of `bytes` is read, so the length of untrusted input should be bounded first, see
+    /// `Limits::check_policy_len`.
     pub fn parse_from_bytes(bytes: &[u8]) -> Result<Self, Self> {
         BitSlice::<Msb0, u8>::from_slice(bytes)
             .into_iter()
@@ -89,6 +93,36 @@ impl<P: Policy> From<Vec<P>> for PolicySet<P> {
     }
 }
 
+/// Borrowed view of a `PolicySet` encoded by `PolicySet::to_bytes`, queried without allocation
+#[derive(Clone, Copy, Debug)]
+pub struct PolicySetRef<'a, P> {
+    bytes: &'a [u8],
+    _p: PhantomData<P>,
+}
+
+impl<'a, P: Policy> PolicySetRef<'a, P> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            _p: PhantomData,
+        }
+    }
+
+    pub fn contains(&self, policy: &P) -> bool {
+        let bits = BitSlice::<Msb0, u8>::from_slice(self.bytes);
+        policy
+            .to_usize()
+            .and_then(|index| bits.get(index))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Owned set, see `PolicySet::parse_from_bytes`
+    pub fn to_policy_set(&self) -> Result<PolicySet<P>, PolicySet<P>> {
+        PolicySet::parse_from_bytes(self.bytes)
+    }
+}
+
 #[inline]
 const fn optimum_vec_length(n: usize) -> usize {
     let bits = u8::BITS as usize;
@@ -118,6 +152,19 @@ mod tests {
         assert_eq!(b1, b2);
     }
 
+    #[test]
+    fn borrowed_view() {
+        let ps: PolicySet<_> = vec![TestPolicy::Policy0, TestPolicy::Policy9].into();
+        let bytes = ps.to_bytes();
+        let view = PolicySetRef::<TestPolicy>::from_bytes(&bytes);
+        assert!(view.contains(&TestPolicy::Policy0));
+        assert!(view.contains(&TestPolicy::Policy9));
+        assert!(!view.contains(&TestPolicy::Policy1));
+        // beyond encoded bits
+        assert!(!view.contains(&TestPolicy::Policy15));
+        assert_eq!(view.to_policy_set().unwrap().to_bytes(), bytes);
+    }
+
     #[test]
     fn test_optimum_vec_length() {
         assert_eq!(optimum_vec_length(0), 0);
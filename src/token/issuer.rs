@@ -0,0 +1,247 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::crypto::{fingerprint, PrivateKey, Signer};
+use crate::error::Error;
+use crate::message::SignedMessage;
+use crate::token::{Clock, PolicyAccessToken, SystemClock};
+
+/// Lifetime of issued tokens, in seconds, unless the issuer is configured otherwise
+pub const DEFAULT_LIFETIME: u64 = 3600;
+
+/// Access token which `TokenIssuer` can stamp with its lifetime
+pub trait IssuableAccessToken: PolicyAccessToken {
+    /// Set issue and expiration time, in seconds since the Unix epoch
+    fn set_lifetime(&mut self, issued_at: u64, expires_at: u64);
+}
+
+/// Encoded token with what the issuer stamped into it
+#[derive(Clone, PartialEq)]
+pub struct IssuedToken {
+    /// `SignedMessage` to hand to the client
+    pub token: String,
+    pub key_id: Option<String>,
+    pub issued_at: u64,
+    pub expires_at: u64,
+}
+
+/// Shows a fingerprint of the token instead of the token, which is a bearer credential
+impl fmt::Debug for IssuedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IssuedToken")
+            .field("token", &fingerprint(self.token.as_bytes()))
+            .field("key_id", &self.key_id)
+            .field("issued_at", &self.issued_at)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+/// Issues tokens which `ValidationAuthority` accepts, the counterpart of the validator on the
+/// identity server
+pub struct TokenIssuer<A, S = PrivateKey> {
+    signer: S,
+    key_id: Option<String>,
+    lifetime: u64,
+    clock: Arc<dyn Clock>,
+    _p: PhantomData<A>,
+}
+
+impl<A: IssuableAccessToken> TokenIssuer<A> {
+    /// Issuer signing with `private_key` under its thumbprint, which is the key id
+    /// `ValidationAuthority::new` registers the public key under
+    pub fn new(private_key: PrivateKey) -> Self {
+        let key_id = private_key.thumbprint();
+        Self::with_signer(private_key, Some(key_id))
+    }
+}
+
+impl<A: IssuableAccessToken, S: Signer> TokenIssuer<A, S> {
+    /// Issuer signing with any `signer`, e.g. a `SecretKey` in symmetric mode or a KMS
+    ///
+    /// `key_id` must be the id of the public key in the key set of validators, tokens without
+    /// key id are verified by every trusted key.
+    pub fn with_signer(signer: S, key_id: Option<String>) -> Self {
+        Self {
+            signer,
+            key_id,
+            lifetime: DEFAULT_LIFETIME,
+            clock: Arc::new(SystemClock),
+            _p: PhantomData,
+        }
+    }
+
+    /// Replace `DEFAULT_LIFETIME` of issued tokens
+    pub fn lifetime(mut self, seconds: u64) -> Self {
+        self.lifetime = seconds;
+        self
+    }
+
+    /// Stamp tokens with the time of `clock` instead of `SystemClock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn issue(&self, access_token: A) -> Result<IssuedToken, Error> {
+        self.issue_for(access_token, self.lifetime, &[])
+    }
+
+    /// Issue token valid for `seconds` instead of the default lifetime, e.g. a short lived token
+    /// for a sensitive operation
+    pub fn issue_with_lifetime(&self, access_token: A, seconds: u64) -> Result<IssuedToken, Error> {
+        self.issue_for(access_token, seconds, &[])
+    }
+
+    /// Issue token bound to `associated_data`, which is only accepted by
+    /// `ValidationAuthority::enforce_in_context` with the same data
+    pub fn issue_in_context(
+        &self,
+        access_token: A,
+        associated_data: &[u8],
+    ) -> Result<IssuedToken, Error> {
+        self.issue_for(access_token, self.lifetime, associated_data)
+    }
+
+    fn issue_for(
+        &self,
+        mut access_token: A,
+        lifetime: u64,
+        associated_data: &[u8],
+    ) -> Result<IssuedToken, Error> {
+        let now = self.clock.now();
+        let expires_at = now.saturating_add(lifetime);
+        access_token.set_lifetime(now, expires_at);
+        let message = SignedMessage::create_with_associated_data(
+            access_token.to_bytes(),
+            &self.signer,
+            self.key_id.clone(),
+            associated_data,
+        )?;
+        Ok(IssuedToken {
+            token: message.encode(),
+            key_id: self.key_id.clone(),
+            issued_at: now,
+            expires_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::tests::{get_test_private_key, get_test_secret_key, MockSigner};
+    use crate::crypto::SecretKey;
+    use crate::error::Error::*;
+    use crate::rbac::test_helpers::TestPolicy::{self, Policy1, Policy2};
+    use crate::rbac::PolicyCond::*;
+    use crate::token::{ManualClock, StandardAccessToken, ValidationAuthority};
+
+    use super::*;
+
+    type AccessToken = StandardAccessToken<TestPolicy>;
+
+    const NOW: u64 = 1_600_000_000;
+
+    fn make_token() -> AccessToken {
+        AccessToken::new(vec![Policy1].into())
+    }
+
+    fn make_issuer_va(
+        clock: Arc<ManualClock>,
+    ) -> (TokenIssuer<AccessToken>, ValidationAuthority<AccessToken>) {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let va = ValidationAuthority::new(private_key.public_key()).with_clock(clock.clone());
+        (TokenIssuer::new(private_key).with_clock(clock), va)
+    }
+
+    #[test]
+    fn issued_token_should_be_accepted() {
+        let (issuer, va) = make_issuer_va(Arc::new(ManualClock::new(NOW)));
+
+        let issued = issuer.issue(make_token()).unwrap();
+        assert_eq!(issued.issued_at, NOW);
+        assert_eq!(issued.expires_at, NOW + DEFAULT_LIFETIME);
+        assert_eq!(
+            issued.key_id,
+            Some(
+                PrivateKey::from_base64(&get_test_private_key())
+                    .unwrap()
+                    .thumbprint()
+            )
+        );
+
+        let access_token = va
+            .enforce(Contains(Policy1), Some(issued.token.as_str()))
+            .unwrap();
+        assert_eq!(access_token.iat, Some(NOW));
+        assert_eq!(access_token.exp, Some(NOW + DEFAULT_LIFETIME));
+        let x = va.enforce(Contains(Policy2), Some(issued.token.as_str()));
+        assert!(matches!(x, Err(Forbidden)));
+    }
+
+    #[test]
+    fn lifetime() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let (issuer, va) = make_issuer_va(clock.clone());
+        let issuer = issuer.lifetime(60);
+
+        let issued = issuer.issue(make_token()).unwrap();
+        assert_eq!(issued.expires_at, NOW + 60);
+        let short_lived = issuer.issue_with_lifetime(make_token(), 10).unwrap();
+        assert_eq!(short_lived.expires_at, NOW + 10);
+
+        clock.advance(10);
+        let x = va.enforce(NoCheck, Some(short_lived.token.as_str()));
+        assert!(matches!(x, Err(ExpiredAccessToken)));
+        assert!(va.enforce(NoCheck, Some(issued.token.as_str())).is_ok());
+
+        clock.set(u64::MAX - 1);
+        let issued = issuer.issue(make_token()).unwrap();
+        assert_eq!(issued.expires_at, u64::MAX);
+    }
+
+    #[test]
+    fn other_signers() {
+        let secret_key = SecretKey::from_base64(&get_test_secret_key()).unwrap();
+        let va = ValidationAuthority::<AccessToken>::with_secret_key(
+            SecretKey::from_base64(&get_test_secret_key()).unwrap(),
+        );
+        let issuer = TokenIssuer::with_signer(secret_key, None);
+        let issued = issuer.issue(make_token()).unwrap();
+        assert_eq!(issued.key_id, None);
+        assert!(va.enforce(NoCheck, Some(issued.token.as_str())).is_ok());
+
+        let issuer = TokenIssuer::<AccessToken, _>::with_signer(MockSigner::unavailable(), None);
+        assert!(matches!(issuer.issue(make_token()), Err(SigningFail)));
+    }
+
+    #[test]
+    fn debug_should_not_show_token() {
+        let (issuer, _) = make_issuer_va(Arc::new(ManualClock::new(NOW)));
+        let issued = issuer.issue(make_token()).unwrap();
+        let debug = format!("{:?}", issued);
+        assert!(!debug.contains(&issued.token));
+        issued
+            .token
+            .split('.')
+            .for_each(|segment| assert!(!debug.contains(segment)));
+        assert!(debug.contains(&fingerprint(issued.token.as_bytes())));
+        assert!(debug.contains(&(NOW + DEFAULT_LIFETIME).to_string()));
+    }
+
+    #[test]
+    fn issue_in_context() {
+        let (issuer, va) = make_issuer_va(Arc::new(ManualClock::new(NOW)));
+
+        let issued = issuer
+            .issue_in_context(make_token(), b"api.example.com")
+            .unwrap();
+        let token = Some(issued.token.as_str());
+        assert!(va
+            .enforce_in_context(NoCheck, token, b"api.example.com")
+            .is_ok());
+        let x = va.enforce(NoCheck, token);
+        assert!(matches!(x, Err(SignatureVerificationFail)));
+    }
+}
@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error::{self, *};
+use crate::rbac::{Policy, PolicySet};
+use crate::token::{IssuableAccessToken, Limits, PolicyAccessToken};
+
+/// JWT claims set (RFC 7519) as an access token, so tokens minted by JOSE issuers are accepted
+///
+/// The payload is a JSON object with the registered claims and the discriminants of the granted
+/// policies in `policies`, e.g. `{"sub":"alice","exp":1600000000,"policies":[1,2]}`. Times are
+/// integer seconds since the Unix epoch, other claims are ignored. Sign it by
+/// `SignedMessage::create_jws` or any JOSE library.
+#[derive(Clone, Debug)]
+pub struct JwtAccessToken<P: Policy> {
+    pub iss: Option<String>,
+    pub sub: Option<String>,
+    /// Either a single string or an array in JSON
+    pub aud: Vec<String>,
+    pub exp: Option<u64>,
+    pub nbf: Option<u64>,
+    pub iat: Option<u64>,
+    pub jti: Option<String>,
+    pub policies: PolicySet<P>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    aud: Option<Audience>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nbf: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    iat: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jti: Option<String>,
+    #[serde(default)]
+    policies: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl<P: Policy> JwtAccessToken<P> {
+    pub fn new(policies: PolicySet<P>) -> Self {
+        Self {
+            iss: None,
+            sub: None,
+            aud: Vec::new(),
+            exp: None,
+            nbf: None,
+            iat: None,
+            jti: None,
+            policies,
+        }
+    }
+
+    /// Decode claims, policies are bounded by `limits`
+    fn decode(buf: &[u8], limits: &Limits) -> Result<Self, Error> {
+        // a claims set is an object, which a struct would also be read from as an array
+        let claims: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(buf).map_err(|_| BadAccessTokenEncoding)?;
+        let claims = Claims::deserialize(serde_json::Value::Object(claims))
+            .map_err(|_| BadAccessTokenEncoding)?;
+        let mut policies = PolicySet::new();
+        for discriminant in claims.policies {
+            // bound like the bits of an encoded `PolicySet`
+            limits.check_policy_len(discriminant / 8 + 1)?;
+            let policy = P::from_usize(discriminant).ok_or(BadAccessTokenEncoding)?;
+            policies.insert(policy);
+        }
+        let aud = match claims.aud {
+            None => Vec::new(),
+            Some(Audience::One(aud)) => vec![aud],
+            Some(Audience::Many(aud)) => aud,
+        };
+        Ok(Self {
+            iss: claims.iss,
+            sub: claims.sub,
+            aud,
+            exp: claims.exp,
+            nbf: claims.nbf,
+            iat: claims.iat,
+            jti: claims.jti,
+            policies,
+        })
+    }
+}
+
+impl<P: Policy> PolicyAccessToken for JwtAccessToken<P> {
+    type Policy = P;
+
+    fn policies(&self) -> &PolicySet<Self::Policy> {
+        &self.policies
+    }
+
+    fn expires_at(&self) -> Option<u64> {
+        self.exp
+    }
+
+    fn not_before(&self) -> Option<u64> {
+        self.nbf
+    }
+
+    fn token_id(&self) -> Option<&str> {
+        self.jti.as_deref()
+    }
+
+    fn subject(&self) -> Option<&str> {
+        self.sub.as_deref()
+    }
+
+    fn issuer(&self) -> Option<&str> {
+        self.iss.as_deref()
+    }
+
+    fn has_audience(&self, audience: &str) -> bool {
+        self.aud.iter().any(|aud| aud == audience)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let aud = match self.aud.as_slice() {
+            [] => None,
+            [aud] => Some(Audience::One(aud.clone())),
+            aud => Some(Audience::Many(aud.to_vec())),
+        };
+        let claims = Claims {
+            iss: self.iss.clone(),
+            sub: self.sub.clone(),
+            aud,
+            exp: self.exp,
+            nbf: self.nbf,
+            iat: self.iat,
+            jti: self.jti.clone(),
+            policies: self
+                .policies
+                .iter()
+                .map(|policy| {
+                    policy
+                        .to_usize()
+                        .expect("Unable to convert Policy to usize")
+                })
+                .collect(),
+        };
+        serde_json::to_vec(&claims).expect("Fail serialize JWT claims")
+    }
+
+    /// Decode token within `Limits::default()`
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        Self::from_bytes_with_limits(buf, &Limits::default()).ok()
+    }
+
+    fn from_bytes_with_limits(buf: &[u8], limits: &Limits) -> Result<Self, Error> {
+        limits.check_payload_len(buf.len())?;
+        Self::decode(buf, limits)
+    }
+}
+
+impl<P: Policy> IssuableAccessToken for JwtAccessToken<P> {
+    fn set_lifetime(&mut self, issued_at: u64, expires_at: u64) {
+        self.iat = Some(issued_at);
+        self.exp = Some(expires_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rbac::test_helpers::TestPolicy::{self, *};
+
+    use super::*;
+
+    type AccessToken = JwtAccessToken<TestPolicy>;
+
+    #[test]
+    fn round_trip() {
+        let mut token = AccessToken::new(vec![Policy1, Policy15].into());
+        token.sub = Some("alice".to_owned());
+        token.exp = Some(1_600_000_000);
+        let json = token.to_bytes();
+        assert_eq!(
+            json,
+            br#"{"sub":"alice","exp":1600000000,"policies":[1,15]}"#.to_vec()
+        );
+        let decoded = AccessToken::from_bytes(&json).unwrap();
+        assert_eq!(decoded.sub.as_deref(), Some("alice"));
+        assert_eq!(decoded.exp, Some(1_600_000_000));
+        assert_eq!(*decoded.policies, *token.policies);
+
+        token.aud = vec!["api".to_owned(), "admin".to_owned()];
+        let decoded = AccessToken::from_bytes(&token.to_bytes()).unwrap();
+        assert_eq!(decoded.aud, token.aud);
+    }
+
+    #[test]
+    fn decode_third_party_claims() {
+        let json = br#"{"iss":"https://issuer.example.com","aud":"api","exp":1600000000,
+            "scope":"read","policies":[2]}"#;
+        let token = AccessToken::from_bytes(json).unwrap();
+        assert_eq!(token.aud, vec!["api".to_owned()]);
+        assert!(token.policies.contains(&Policy2));
+
+        // no policies
+        let token = AccessToken::from_bytes(br#"{"exp":1600000000}"#).unwrap();
+        assert!(token.policies.is_empty());
+
+        // unknown policy, time which isn't an integer
+        assert!(AccessToken::from_bytes(br#"{"policies":[23]}"#).is_none());
+        assert!(AccessToken::from_bytes(br#"{"exp":1600000000.5}"#).is_none());
+        assert!(AccessToken::from_bytes(b"[]").is_none());
+
+        let limits = Limits {
+            max_policy_len: 1,
+            ..Limits::default()
+        };
+        let x = AccessToken::from_bytes_with_limits(br#"{"policies":[8]}"#, &limits);
+        assert!(matches!(x, Err(LimitExceeded)));
+    }
+}
@@ -0,0 +1,47 @@
+use crate::error::Error::{self, *};
+
+/// Bounds on untrusted tokens, checked before the work they bound so an oversized token is
+/// rejected by `Error::LimitExceeded` before it costs decoding or hashing time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// Length of the encoded token, checked before anything is decoded
+    pub max_token_len: usize,
+    /// Length of the signed payload, checked from its encoding before it's decoded
+    pub max_payload_len: usize,
+    /// Length of an encoded `PolicySet`, checked before its bits are read
+    pub max_policy_len: usize,
+}
+
+impl Default for Limits {
+    /// Generous bounds which fit tokens carrying a few thousand policies
+    fn default() -> Self {
+        Self {
+            max_token_len: 16 * 1024,
+            max_payload_len: 8 * 1024,
+            max_policy_len: 256,
+        }
+    }
+}
+
+impl Limits {
+    pub fn check_token_len(&self, len: usize) -> Result<(), Error> {
+        check(len, self.max_token_len)
+    }
+
+    pub fn check_payload_len(&self, len: usize) -> Result<(), Error> {
+        check(len, self.max_payload_len)
+    }
+
+    /// Called by `PolicyAccessToken::from_bytes_with_limits` before `PolicySet::parse_from_bytes`
+    pub fn check_policy_len(&self, len: usize) -> Result<(), Error> {
+        check(len, self.max_policy_len)
+    }
+}
+
+fn check(len: usize, max: usize) -> Result<(), Error> {
+    if len > max {
+        Err(LimitExceeded)
+    } else {
+        Ok(())
+    }
+}
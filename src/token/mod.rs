@@ -1,17 +1,97 @@
 use std::ops::Deref;
 
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use issuer::{IssuableAccessToken, IssuedToken, TokenIssuer, DEFAULT_LIFETIME};
+pub use jwt::JwtAccessToken;
+pub use limits::Limits;
+pub use replay::ReplayCache;
+pub use revocation::{FileRevocationStore, MemoryRevocationStore, RevocationKey, RevocationStore};
+pub use standard::{ExtensionClaims, StandardAccessToken};
 pub use validator::AccessEnforcer;
-pub use validator::ValidationAuthority;
+pub use validator::{SignaturePolicy, ValidationAuthority};
 
-use crate::rbac::{Policy, PolicySet};
+use crate::error::Error::{self, *};
+use crate::rbac::{Policy, PolicySet, PolicySetRef};
 
 pub trait PolicyAccessToken: Sized {
     type Policy: Policy;
 
     fn policies(&self) -> &PolicySet<Self::Policy>;
-    fn is_expired(&self) -> bool;
+
+    /// Expiration time in seconds since the Unix epoch, checked by `ValidationAuthority`
+    ///
+    /// A token without expiration time is rejected as expired.
+    fn expires_at(&self) -> Option<u64>;
+
+    /// Time before which the token isn't valid yet, if any
+    fn not_before(&self) -> Option<u64> {
+        None
+    }
+
+    /// Id of the token, which a `RevocationStore` may revoke
+    fn token_id(&self) -> Option<&str> {
+        None
+    }
+
+    /// Subject of the token, every token of which a `RevocationStore` may revoke
+    fn subject(&self) -> Option<&str> {
+        None
+    }
+
+    /// Issuer of the token, checked by `ValidationAuthority::expect_issuer`
+    fn issuer(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether the token is intended for `audience`, checked by
+    /// `ValidationAuthority::expect_audience`
+    fn has_audience(&self, _audience: &str) -> bool {
+        false
+    }
+
     fn to_bytes(&self) -> Vec<u8>;
     fn from_bytes(buf: &[u8]) -> Option<Self>;
+
+    /// Decode access token within `limits`, which is what `ValidationAuthority` calls
+    ///
+    /// Tokens carrying a `PolicySet` should override this to check `Limits::check_policy_len`
+    /// before parsing the set.
+    fn from_bytes_with_limits(buf: &[u8], limits: &Limits) -> Result<Self, Error> {
+        limits.check_payload_len(buf.len())?;
+        Self::from_bytes(buf).ok_or(BadAccessTokenEncoding)
+    }
+}
+
+/// Borrowed view of an access token, read from a verified message without allocation
+///
+/// Optional counterpart of `PolicyAccessToken` for `ValidationAuthority::enforce_ref`.
+pub trait PolicyAccessTokenRef<'a>: Sized {
+    type Policy: Policy;
+
+    fn policies(&self) -> PolicySetRef<'a, Self::Policy>;
+    fn expires_at(&self) -> Option<u64>;
+
+    fn not_before(&self) -> Option<u64> {
+        None
+    }
+
+    fn token_id(&self) -> Option<&'a str> {
+        None
+    }
+
+    fn subject(&self) -> Option<&'a str> {
+        None
+    }
+
+    fn issuer(&self) -> Option<&'a str> {
+        None
+    }
+
+    fn has_audience(&self, _audience: &str) -> bool {
+        false
+    }
+
+    fn from_bytes(buf: &'a [u8]) -> Option<Self>;
 }
 
 pub trait ToTokenStr {
@@ -24,7 +104,25 @@ impl<T: Deref<Target = str>> ToTokenStr for Option<T> {
     }
 }
 
+/// Token in binary form, e.g. a COSE_Sign1 message from a constrained client
+pub trait ToTokenBytes {
+    fn to_token_bytes(&self) -> Option<&[u8]>;
+}
+
+impl<T: Deref<Target = [u8]>> ToTokenBytes for Option<T> {
+    fn to_token_bytes(&self) -> Option<&[u8]> {
+        self.as_deref()
+    }
+}
+
 #[cfg(test)]
 pub mod test_utils;
 
+mod clock;
+mod issuer;
+mod jwt;
+mod limits;
+mod replay;
+mod revocation;
+mod standard;
 mod validator;
@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+
+use crate::error::Error::{self, *};
+
+/// Ids of the tokens presented to `ValidationAuthority`, so one-time tokens are accepted once
+///
+/// An id is kept until its token expires. Ids are indexed by expiration time as well, so expired
+/// ids are dropped from the oldest one as new ids are recorded, without scanning the cache.
+///
+/// The cache holds at most `capacity` ids. When it's full of ids of tokens which haven't expired,
+/// further tokens are rejected by `Error::LimitExceeded` rather than forgetting an id which could
+/// then be replayed. Size it for the number of one-time tokens issued within a token lifetime.
+pub struct ReplayCache {
+    entries: Mutex<Entries>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct Entries {
+    seen: HashSet<String>,
+    /// Recorded ids by the time they expire
+    by_expiration: BTreeMap<u64, Vec<String>>,
+}
+
+impl Entries {
+    /// Drop ids which expire at or before `now`
+    fn purge(&mut self, now: u64) {
+        while let Some(expires_at) = self.by_expiration.keys().next().copied() {
+            if expires_at > now {
+                break;
+            }
+            for id in self.by_expiration.remove(&expires_at).unwrap_or_default() {
+                self.seen.remove(&id);
+            }
+        }
+    }
+
+    fn insert(&mut self, token_id: &str, expires_at: u64) {
+        self.seen.insert(token_id.to_owned());
+        self.by_expiration
+            .entry(expires_at)
+            .or_default()
+            .push(token_id.to_owned());
+    }
+}
+
+impl ReplayCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(Entries::default()),
+            capacity,
+        }
+    }
+
+    /// Record `token_id` until `expires_at`, `Error::Replayed` if it's already recorded
+    ///
+    /// `now` is the time of the validator, in seconds since the Unix epoch, ids which expire at or
+    /// before it are dropped.
+    pub fn check_and_record(&self, token_id: &str, expires_at: u64, now: u64) -> Result<(), Error> {
+        let mut entries = self.entries.lock().expect("Poisoned replay cache");
+        entries.purge(now);
+        if entries.seen.contains(token_id) {
+            return Err(Replayed);
+        }
+        if entries.seen.len() >= self.capacity {
+            return Err(LimitExceeded);
+        }
+        entries.insert(token_id, expires_at);
+        Ok(())
+    }
+
+    /// Number of recorded ids, including expired ones which aren't dropped yet
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .expect("Poisoned replay cache")
+            .seen
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    const NOW: u64 = 1_600_000_000;
+
+    #[test]
+    fn replay() {
+        let cache = ReplayCache::new(2);
+        assert!(cache.check_and_record("t1", NOW + 60, NOW).is_ok());
+        assert!(matches!(
+            cache.check_and_record("t1", NOW + 60, NOW),
+            Err(Replayed)
+        ));
+        assert!(cache.check_and_record("t2", NOW + 120, NOW).is_ok());
+
+        // full of live ids
+        assert!(matches!(
+            cache.check_and_record("t3", NOW + 60, NOW),
+            Err(LimitExceeded)
+        ));
+
+        // expired ids make room, an expired token can't be replayed anyway
+        assert!(cache.check_and_record("t3", NOW + 180, NOW + 60).is_ok());
+        assert_eq!(cache.len(), 2);
+        assert!(matches!(
+            cache.check_and_record("t2", NOW + 120, NOW + 60),
+            Err(Replayed)
+        ));
+
+        // every id expiring at the same time is dropped together
+        let cache = ReplayCache::new(4);
+        for id in &["t1", "t2", "t3"] {
+            assert!(cache.check_and_record(id, NOW + 60, NOW).is_ok());
+        }
+        assert!(cache.check_and_record("t4", NOW + 120, NOW).is_ok());
+        assert!(cache.check_and_record("t5", NOW + 120, NOW + 60).is_ok());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.check_and_record("t1", NOW + 120, NOW + 60).is_ok());
+    }
+
+    #[test]
+    fn shared_across_threads() {
+        let cache = Arc::new(ReplayCache::new(16));
+        let accepted = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                std::thread::spawn(move || cache.check_and_record("t1", u64::MAX, NOW).is_ok())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .filter(|accepted| *accepted)
+            .count();
+        assert_eq!(accepted, 1);
+    }
+}
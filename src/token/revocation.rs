@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::token::{Clock, SystemClock};
+
+/// What a revocation applies to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RevocationKey<'a> {
+    /// A single token, by its `jti`
+    TokenId(&'a str),
+    /// Every token of a subject, by its `sub`
+    Subject(&'a str),
+}
+
+impl<'a> RevocationKey<'a> {
+    fn id(self) -> &'a str {
+        match self {
+            RevocationKey::TokenId(id) | RevocationKey::Subject(id) => id,
+        }
+    }
+}
+
+/// Revoked tokens, consulted by `ValidationAuthority` once a token is verified
+pub trait RevocationStore: Send + Sync {
+    fn is_revoked(&self, key: RevocationKey<'_>) -> bool;
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Revoked token ids and subjects, with the time their revocation expires
+    token_ids: HashMap<String, u64>,
+    subjects: HashMap<String, u64>,
+}
+
+impl Entries {
+    fn map(&self, key: RevocationKey<'_>) -> &HashMap<String, u64> {
+        match key {
+            RevocationKey::TokenId(_) => &self.token_ids,
+            RevocationKey::Subject(_) => &self.subjects,
+        }
+    }
+
+    fn map_mut(&mut self, key: RevocationKey<'_>) -> &mut HashMap<String, u64> {
+        match key {
+            RevocationKey::TokenId(_) => &mut self.token_ids,
+            RevocationKey::Subject(_) => &mut self.subjects,
+        }
+    }
+
+    fn purge(&mut self, now: u64) {
+        self.token_ids.retain(|_, expires_at| *expires_at > now);
+        self.subjects.retain(|_, expires_at| *expires_at > now);
+    }
+}
+
+/// Revocations held in memory, lost on restart
+///
+/// A revocation is kept until it expires, which should be when the revoked tokens expire, then
+/// it's purged by the next `revoke` or `purge`.
+pub struct MemoryRevocationStore {
+    entries: RwLock<Entries>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for MemoryRevocationStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryRevocationStore {
+    pub fn new() -> Self {
+        Self {
+            entries: RwLock::new(Entries::default()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Expire revocations by the time of `clock` instead of `SystemClock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Revoke `key` until `expires_at`, in seconds since the Unix epoch
+    ///
+    /// Revoking a key again keeps the later expiration time.
+    pub fn revoke(&self, key: RevocationKey<'_>, expires_at: u64) {
+        self.purge();
+        self.insert(key, expires_at);
+    }
+
+    fn insert(&self, key: RevocationKey<'_>, expires_at: u64) {
+        let mut entries = self.entries.write().expect("Poisoned revocation store");
+        let entry = entries.map_mut(key).entry(key.id().to_owned()).or_default();
+        *entry = (*entry).max(expires_at);
+    }
+
+    /// Drop expired revocations
+    pub fn purge(&self) {
+        let mut entries = self.entries.write().expect("Poisoned revocation store");
+        entries.purge(self.clock.now());
+    }
+
+    /// Number of revocations, including expired ones which aren't purged yet
+    pub fn len(&self) -> usize {
+        let entries = self.entries.read().expect("Poisoned revocation store");
+        entries.token_ids.len() + entries.subjects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn for_each_live(&self, mut f: impl FnMut(RevocationKey<'_>, u64)) {
+        let now = self.clock.now();
+        let entries = self.entries.read().expect("Poisoned revocation store");
+        let token_ids = entries
+            .token_ids
+            .iter()
+            .map(|(id, expires_at)| (RevocationKey::TokenId(id), *expires_at));
+        let subjects = entries
+            .subjects
+            .iter()
+            .map(|(subject, expires_at)| (RevocationKey::Subject(subject), *expires_at));
+        token_ids
+            .chain(subjects)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .for_each(|(key, expires_at)| f(key, expires_at));
+    }
+}
+
+impl RevocationStore for MemoryRevocationStore {
+    fn is_revoked(&self, key: RevocationKey<'_>) -> bool {
+        let entries = self.entries.read().expect("Poisoned revocation store");
+        entries
+            .map(key)
+            .get(key.id())
+            .is_some_and(|expires_at| *expires_at > self.clock.now())
+    }
+}
+
+/// Revocations persisted to a file, which survive restarts
+///
+/// The file is a log with one revocation per line, `token <expires_at> <id>` or
+/// `subject <expires_at> <subject>`, where `%`, whitespace and control characters of ids are
+/// percent-encoded. Revocations are appended and synced before `revoke` returns,
+/// the log is compacted to the revocations which haven't expired when it's opened and by
+/// `purge`. Lookups are served from memory.
+///
+/// Expired revocations stay in the log until it's compacted, so a long running service should
+/// call `purge` periodically, e.g. once per token lifetime, to keep the log from growing without
+/// bound.
+pub struct FileRevocationStore {
+    memory: MemoryRevocationStore,
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+const TOKEN_ID_RECORD: &str = "token";
+const SUBJECT_RECORD: &str = "subject";
+
+impl FileRevocationStore {
+    /// Open store at `path`, which is created if it doesn't exist
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with_clock(path, Arc::new(SystemClock))
+    }
+
+    /// Open store at `path` expiring revocations by the time of `clock`
+    pub fn open_with_clock(path: impl AsRef<Path>, clock: Arc<dyn Clock>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let memory = MemoryRevocationStore::new().with_clock(clock);
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    let (kind, expires_at, id) = parse_record(&line).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "Bad revocation record")
+                    })?;
+                    let key = match kind {
+                        TOKEN_ID_RECORD => RevocationKey::TokenId(&id),
+                        _ => RevocationKey::Subject(&id),
+                    };
+                    memory.insert(key, expires_at);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let file = Self::compact(&path, &memory)?;
+        Ok(Self {
+            memory,
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Rewrite the log with live revocations only, atomically replacing the previous one
+    fn compact(path: &Path, memory: &MemoryRevocationStore) -> io::Result<File> {
+        let mut compacted = path.as_os_str().to_owned();
+        compacted.push(".tmp");
+        let compacted = PathBuf::from(compacted);
+        let mut file = File::create(&compacted)?;
+        let mut records = String::new();
+        memory.for_each_live(|key, expires_at| records.push_str(&format_record(key, expires_at)));
+        file.write_all(records.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&compacted, path)?;
+        OpenOptions::new().append(true).open(path)
+    }
+
+    /// Revoke `key` until `expires_at`, see `MemoryRevocationStore::revoke`
+    pub fn revoke(&self, key: RevocationKey<'_>, expires_at: u64) -> io::Result<()> {
+        let mut file = self.file.lock().expect("Poisoned revocation store");
+        file.write_all(format_record(key, expires_at).as_bytes())?;
+        file.sync_data()?;
+        self.memory.revoke(key, expires_at);
+        Ok(())
+    }
+
+    /// Drop expired revocations and compact the log to the live ones
+    pub fn purge(&self) -> io::Result<()> {
+        let mut file = self.file.lock().expect("Poisoned revocation store");
+        self.memory.purge();
+        *file = Self::compact(&self.path, &self.memory)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl RevocationStore for FileRevocationStore {
+    fn is_revoked(&self, key: RevocationKey<'_>) -> bool {
+        self.memory.is_revoked(key)
+    }
+}
+
+fn format_record(key: RevocationKey<'_>, expires_at: u64) -> String {
+    let kind = match key {
+        RevocationKey::TokenId(_) => TOKEN_ID_RECORD,
+        RevocationKey::Subject(_) => SUBJECT_RECORD,
+    };
+    format!("{} {} {}\n", kind, expires_at, escape(key.id()))
+}
+
+/// Kind, expiration time and unescaped id of a record
+fn parse_record(line: &str) -> Option<(&str, u64, String)> {
+    let mut fields = line.splitn(3, ' ');
+    let kind = fields
+        .next()
+        .filter(|kind| *kind == TOKEN_ID_RECORD || *kind == SUBJECT_RECORD)?;
+    let expires_at = fields.next()?.parse().ok()?;
+    let id = unescape(fields.next()?)?;
+    Some((kind, expires_at, id))
+}
+
+/// Percent-encode `%`, whitespace and control characters so an id is a single field of a line
+fn escape(id: &str) -> String {
+    let mut escaped = String::with_capacity(id.len());
+    for c in id.chars() {
+        if c == '%' || c.is_whitespace() || c.is_control() {
+            let mut buf = [0u8; 4];
+            c.encode_utf8(&mut buf)
+                .bytes()
+                .for_each(|b| escaped.push_str(&format!("%{:02X}", b)));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::token::ManualClock;
+
+    use super::*;
+
+    const NOW: u64 = 1_600_000_000;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tokidator-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn memory_store() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let store = MemoryRevocationStore::new().with_clock(clock.clone());
+        store.revoke(RevocationKey::TokenId("t1"), NOW + 60);
+        store.revoke(RevocationKey::Subject("alice"), NOW + 120);
+        assert!(store.is_revoked(RevocationKey::TokenId("t1")));
+        assert!(store.is_revoked(RevocationKey::Subject("alice")));
+        // token ids and subjects are apart
+        assert!(!store.is_revoked(RevocationKey::Subject("t1")));
+        assert!(!store.is_revoked(RevocationKey::TokenId("t2")));
+
+        // the later expiration is kept
+        store.revoke(RevocationKey::TokenId("t1"), NOW + 30);
+        clock.advance(60);
+        assert!(!store.is_revoked(RevocationKey::TokenId("t1")));
+        assert!(store.is_revoked(RevocationKey::Subject("alice")));
+        assert_eq!(store.len(), 2);
+
+        store.revoke(RevocationKey::TokenId("t2"), NOW + 600);
+        assert_eq!(store.len(), 2);
+        clock.advance(60);
+        store.purge();
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn file_store() {
+        let path = temp_path("revocations");
+        let _ = fs::remove_file(&path);
+        let clock = Arc::new(ManualClock::new(NOW));
+        let store = FileRevocationStore::open_with_clock(&path, clock.clone()).unwrap();
+        store
+            .revoke(RevocationKey::TokenId("t1"), NOW + 60)
+            .unwrap();
+        store
+            .revoke(RevocationKey::Subject("alice"), NOW + 120)
+            .unwrap();
+        store
+            .revoke(RevocationKey::TokenId("t 2%\n"), NOW + 120)
+            .unwrap();
+        drop(store);
+
+        // revocations survive restart, expired ones are compacted away
+        clock.advance(60);
+        let store = FileRevocationStore::open_with_clock(&path, clock.clone()).unwrap();
+        assert!(!store.is_revoked(RevocationKey::TokenId("t1")));
+        assert!(store.is_revoked(RevocationKey::Subject("alice")));
+        assert!(store.is_revoked(RevocationKey::TokenId("t 2%\n")));
+        let mut records: Vec<String> = fs::read_to_string(store.path())
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        records.sort();
+        assert_eq!(
+            records,
+            vec![
+                format!("subject {} alice", NOW + 120),
+                format!("token {} t%202%25%0A", NOW + 120),
+            ]
+        );
+
+        // purge compacts the log, which is still appended to
+        store
+            .revoke(RevocationKey::TokenId("t3"), NOW + 600)
+            .unwrap();
+        clock.advance(60);
+        store.purge().unwrap();
+        store
+            .revoke(RevocationKey::TokenId("t4"), NOW + 600)
+            .unwrap();
+        let mut records: Vec<String> = fs::read_to_string(store.path())
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        records.sort();
+        assert_eq!(
+            records,
+            vec![
+                format!("token {} t3", NOW + 600),
+                format!("token {} t4", NOW + 600)
+            ]
+        );
+        drop(store);
+
+        fs::write(&path, "token soon t1\n").unwrap();
+        assert!(FileRevocationStore::open(&path).is_err());
+        fs::write(&path, format!("token {} t%2\n", NOW)).unwrap();
+        assert!(FileRevocationStore::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,392 @@
+use crate::error::Error::{self, *};
+use crate::rbac::{Policy, PolicySet};
+use crate::token::{IssuableAccessToken, Limits, PolicyAccessToken};
+
+/// Version of the encoding, the first byte of an encoded token
+const ENCODING_VERSION: u8 = 1;
+// second byte flags the claims which follow it, in this order
+const HAS_ISS: u8 = 1;
+const HAS_SUB: u8 = 1 << 1;
+const HAS_AUD: u8 = 1 << 2;
+const HAS_EXP: u8 = 1 << 3;
+const HAS_NBF: u8 = 1 << 4;
+const HAS_IAT: u8 = 1 << 5;
+const HAS_JTI: u8 = 1 << 6;
+const HAS_EXTENSION: u8 = 1 << 7;
+/// Longest LEB128 encoding of a `u64`
+const MAX_VARINT_LEN: usize = 10;
+
+/// Claims of an application carried by `StandardAccessToken`, e.g. a tenant id
+pub trait ExtensionClaims: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(buf: &[u8]) -> Option<Self>;
+}
+
+/// No extension, a token carrying extension claims is rejected
+impl ExtensionClaims for () {
+    fn to_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.is_empty() {
+            Some(())
+        } else {
+            None
+        }
+    }
+}
+
+/// Access token with the registered claims of JWT (RFC 7519), times are seconds since the Unix
+/// epoch
+///
+/// Encoded as a version byte, a byte flagging the claims which are present, then every present
+/// claim: strings and bytes prefixed by their LEB128 length, times as LEB128 integers. The
+/// length-prefixed `PolicySet` and extension claims come last. `ValidationAuthority` rejects a
+/// token without `exp` as expired, `TokenIssuer` stamps `iat` and `exp`.
+#[derive(Clone, Debug)]
+pub struct StandardAccessToken<P: Policy, E = ()> {
+    pub iss: Option<String>,
+    pub sub: Option<String>,
+    pub aud: Option<String>,
+    pub exp: Option<u64>,
+    pub nbf: Option<u64>,
+    pub iat: Option<u64>,
+    pub jti: Option<String>,
+    pub policies: PolicySet<P>,
+    pub extension: E,
+}
+
+impl<P: Policy, E: ExtensionClaims + Default> StandardAccessToken<P, E> {
+    pub fn new(policies: PolicySet<P>) -> Self {
+        Self {
+            iss: None,
+            sub: None,
+            aud: None,
+            exp: None,
+            nbf: None,
+            iat: None,
+            jti: None,
+            policies,
+            extension: E::default(),
+        }
+    }
+}
+
+impl<P: Policy, E: ExtensionClaims> StandardAccessToken<P, E> {
+    /// Decode token, its policies are bounded by `limits`
+    fn decode(buf: &[u8], limits: &Limits) -> Result<Self, Error> {
+        let mut reader = Reader(buf);
+        if reader.byte()? != ENCODING_VERSION {
+            return Err(BadAccessTokenEncoding);
+        }
+        let flags = reader.byte()?;
+        let has = |flag| flags & flag != 0;
+        let iss = reader.text_if(has(HAS_ISS))?;
+        let sub = reader.text_if(has(HAS_SUB))?;
+        let aud = reader.text_if(has(HAS_AUD))?;
+        let exp = reader.varint_if(has(HAS_EXP))?;
+        let nbf = reader.varint_if(has(HAS_NBF))?;
+        let iat = reader.varint_if(has(HAS_IAT))?;
+        let jti = reader.text_if(has(HAS_JTI))?;
+        let policies = reader.bytes()?;
+        limits.check_policy_len(policies.len())?;
+        let policies = PolicySet::parse_from_bytes(policies).map_err(|_| BadAccessTokenEncoding)?;
+        let extension = if has(HAS_EXTENSION) {
+            reader.bytes()?
+        } else {
+            &[]
+        };
+        let extension = E::from_bytes(extension).ok_or(BadAccessTokenEncoding)?;
+        if !reader.0.is_empty() {
+            return Err(BadAccessTokenEncoding);
+        }
+        Ok(Self {
+            iss,
+            sub,
+            aud,
+            exp,
+            nbf,
+            iat,
+            jti,
+            policies,
+            extension,
+        })
+    }
+}
+
+impl<P: Policy, E: ExtensionClaims> PolicyAccessToken for StandardAccessToken<P, E> {
+    type Policy = P;
+
+    fn policies(&self) -> &PolicySet<Self::Policy> {
+        &self.policies
+    }
+
+    fn expires_at(&self) -> Option<u64> {
+        self.exp
+    }
+
+    fn not_before(&self) -> Option<u64> {
+        self.nbf
+    }
+
+    fn token_id(&self) -> Option<&str> {
+        self.jti.as_deref()
+    }
+
+    fn subject(&self) -> Option<&str> {
+        self.sub.as_deref()
+    }
+
+    fn issuer(&self) -> Option<&str> {
+        self.iss.as_deref()
+    }
+
+    fn has_audience(&self, audience: &str) -> bool {
+        self.aud.as_deref() == Some(audience)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let extension = self.extension.to_bytes();
+        let flag = |present: bool, flag: u8| if present { flag } else { 0 };
+        let flags = flag(self.iss.is_some(), HAS_ISS)
+            | flag(self.sub.is_some(), HAS_SUB)
+            | flag(self.aud.is_some(), HAS_AUD)
+            | flag(self.exp.is_some(), HAS_EXP)
+            | flag(self.nbf.is_some(), HAS_NBF)
+            | flag(self.iat.is_some(), HAS_IAT)
+            | flag(self.jti.is_some(), HAS_JTI)
+            | flag(!extension.is_empty(), HAS_EXTENSION);
+
+        let mut out = vec![ENCODING_VERSION, flags];
+        let texts = [&self.iss, &self.sub, &self.aud];
+        texts.iter().copied().flatten().for_each(|text| {
+            write_bytes(text.as_bytes(), &mut out);
+        });
+        let times = [self.exp, self.nbf, self.iat];
+        times
+            .iter()
+            .flatten()
+            .for_each(|time| write_varint(*time, &mut out));
+        if let Some(jti) = &self.jti {
+            write_bytes(jti.as_bytes(), &mut out);
+        }
+        write_bytes(&self.policies.to_bytes(), &mut out);
+        if !extension.is_empty() {
+            write_bytes(&extension, &mut out);
+        }
+        out
+    }
+
+    /// Decode token within `Limits::default()`
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        Self::from_bytes_with_limits(buf, &Limits::default()).ok()
+    }
+
+    fn from_bytes_with_limits(buf: &[u8], limits: &Limits) -> Result<Self, Error> {
+        limits.check_payload_len(buf.len())?;
+        Self::decode(buf, limits)
+    }
+}
+
+impl<P: Policy, E: ExtensionClaims> IssuableAccessToken for StandardAccessToken<P, E> {
+    fn set_lifetime(&mut self, issued_at: u64, expires_at: u64) {
+        self.iat = Some(issued_at);
+        self.exp = Some(expires_at);
+    }
+}
+
+fn write_varint(mut n: u64, out: &mut Vec<u8>) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    write_varint(bytes.len() as u64, out);
+    out.extend_from_slice(bytes);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, Error> {
+        let (first, rest) = self.0.split_first().ok_or(BadAccessTokenEncoding)?;
+        self.0 = rest;
+        Ok(*first)
+    }
+
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut n = 0u64;
+        for i in 0..MAX_VARINT_LEN {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            // the last byte holds the most significant bit only
+            if i == MAX_VARINT_LEN - 1 && bits > 1 {
+                return Err(BadAccessTokenEncoding);
+            }
+            n |= bits << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(BadAccessTokenEncoding)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.varint()?;
+        if len > self.0.len() as u64 {
+            return Err(BadAccessTokenEncoding);
+        }
+        let (bytes, rest) = self.0.split_at(len as usize);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn varint_if(&mut self, present: bool) -> Result<Option<u64>, Error> {
+        if present {
+            self.varint().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn text_if(&mut self, present: bool) -> Result<Option<String>, Error> {
+        if !present {
+            return Ok(None);
+        }
+        let text = std::str::from_utf8(self.bytes()?).map_err(|_| BadAccessTokenEncoding)?;
+        Ok(Some(text.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use crate::crypto::tests::get_test_private_key;
+    use crate::crypto::PrivateKey;
+    use crate::rbac::test_helpers::TestPolicy::{self, *};
+    use crate::rbac::PolicyCond::*;
+    use crate::token::{TokenIssuer, ValidationAuthority};
+
+    use super::*;
+
+    type AccessToken = StandardAccessToken<TestPolicy>;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Tenant(u32);
+
+    impl ExtensionClaims for Tenant {
+        fn to_bytes(&self) -> Vec<u8> {
+            self.0.to_be_bytes().to_vec()
+        }
+
+        fn from_bytes(buf: &[u8]) -> Option<Self> {
+            let bytes = <[u8; 4]>::try_from(buf).ok()?;
+            Some(Self(u32::from_be_bytes(bytes)))
+        }
+    }
+
+    fn full_token() -> StandardAccessToken<TestPolicy, Tenant> {
+        StandardAccessToken {
+            iss: Some("https://id.example.com".to_owned()),
+            sub: Some("user-1".to_owned()),
+            aud: Some("api.example.com".to_owned()),
+            exp: Some(u64::MAX),
+            nbf: Some(1_600_000_000),
+            iat: Some(1_600_000_000),
+            jti: Some("c2a5".to_owned()),
+            policies: vec![Policy1, Policy9].into(),
+            extension: Tenant(42),
+        }
+    }
+
+    #[test]
+    fn encoding() {
+        let token = AccessToken::new(vec![Policy1].into());
+        // version, flags, policies
+        assert_eq!(token.to_bytes(), vec![1, 0, 1, 0b0100_0000]);
+
+        let token = full_token();
+        let bytes = token.to_bytes();
+        assert_eq!(&bytes[..2], &[1, 0xff]);
+        let decoded = StandardAccessToken::<TestPolicy, Tenant>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.iss, token.iss);
+        assert_eq!(decoded.sub, token.sub);
+        assert_eq!(decoded.aud, token.aud);
+        assert_eq!(decoded.exp, token.exp);
+        assert_eq!(decoded.nbf, token.nbf);
+        assert_eq!(decoded.iat, token.iat);
+        assert_eq!(decoded.jti, token.jti);
+        assert_eq!(*decoded.policies, *token.policies);
+        assert_eq!(decoded.extension, Tenant(42));
+
+        // token with extension isn't read without it
+        assert!(AccessToken::from_bytes(&bytes).is_none());
+    }
+
+    #[test]
+    fn decode_should_reject_malformed() {
+        let bytes = full_token().to_bytes();
+        let decode = StandardAccessToken::<TestPolicy, Tenant>::from_bytes;
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_none());
+        }
+        assert!(decode(&[bytes.as_slice(), &[0]].concat()).is_none());
+        assert!(decode(&[&[2], &bytes[1..]].concat()).is_none());
+        // exp longer than u64
+        let overflow = [
+            1, HAS_EXP, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02, 0,
+        ];
+        assert!(AccessToken::from_bytes(&overflow).is_none());
+        // invalid utf-8 subject
+        assert!(AccessToken::from_bytes(&[1, HAS_SUB, 1, 0xff, 0]).is_none());
+        // unknown policy
+        assert!(AccessToken::from_bytes(&[1, 0, 3, 0, 0, 0x80]).is_none());
+    }
+
+    #[test]
+    fn limits() {
+        let bytes = full_token().to_bytes();
+        let limits = Limits {
+            max_policy_len: 1,
+            ..Limits::default()
+        };
+        let x = StandardAccessToken::<TestPolicy, Tenant>::from_bytes_with_limits(&bytes, &limits);
+        assert!(matches!(x, Err(LimitExceeded)));
+
+        // default limits apply without explicit ones
+        let policy_len = Limits::default().max_policy_len + 1;
+        let mut bytes = vec![ENCODING_VERSION, 0];
+        write_bytes(&vec![0; policy_len], &mut bytes);
+        assert!(AccessToken::from_bytes(&bytes).is_none());
+        let x = AccessToken::from_bytes_with_limits(
+            &bytes,
+            &Limits {
+                max_policy_len: policy_len,
+                ..Limits::default()
+            },
+        );
+        assert!(x.is_ok());
+    }
+
+    #[test]
+    fn issue_and_enforce() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let va = ValidationAuthority::<AccessToken>::new(private_key.public_key());
+        let issuer = TokenIssuer::new(private_key);
+
+        let mut token = AccessToken::new(vec![Policy1].into());
+        token.sub = Some("user-1".to_owned());
+        let issued = issuer.issue(token).unwrap();
+        let access_token = va
+            .enforce(Contains(Policy1), Some(issued.token.as_str()))
+            .unwrap();
+        assert_eq!(access_token.sub.as_deref(), Some("user-1"));
+        assert_eq!(access_token.iat, Some(issued.issued_at));
+        assert_eq!(access_token.exp, Some(issued.expires_at));
+    }
+}
@@ -1,8 +1,9 @@
-use protobuf::{parse_from_bytes, Message};
+use protobuf::Message;
 
+use crate::error::Error::{self, *};
 use crate::rbac::test_helpers::TestPolicy;
-use crate::rbac::PolicySet;
-use crate::token::PolicyAccessToken;
+use crate::rbac::{PolicySet, PolicySetRef};
+use crate::token::{Limits, PolicyAccessToken, PolicyAccessTokenRef};
 
 #[derive(Debug)]
 pub struct TestAccessToken {
@@ -23,8 +24,9 @@ impl PolicyAccessToken for TestAccessToken {
         &self.policies
     }
 
-    fn is_expired(&self) -> bool {
-        self.expired
+    /// Expired tokens expired at the epoch, the others never expire
+    fn expires_at(&self) -> Option<u64> {
+        Some(if self.expired { 0 } else { u64::MAX })
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -38,9 +40,67 @@ impl PolicyAccessToken for TestAccessToken {
     }
 
     fn from_bytes(buf: &[u8]) -> Option<Self> {
-        let token = parse_from_bytes::<crate::protos::TestAccessToken>(buf).ok()?;
+        let token = crate::protos::TestAccessToken::parse_from_bytes(buf).ok()?;
         let ps = PolicySet::parse_from_bytes(token.policies.as_slice())
             .expect("Bad encoded test policies");
         Some(Self::new(ps, token.expired))
     }
+
+    fn from_bytes_with_limits(buf: &[u8], limits: &Limits) -> Result<Self, Error> {
+        limits.check_payload_len(buf.len())?;
+        let token = crate::protos::TestAccessToken::parse_from_bytes(buf)
+            .map_err(|_| BadAccessTokenEncoding)?;
+        limits.check_policy_len(token.policies.len())?;
+        let ps = PolicySet::parse_from_bytes(token.policies.as_slice())
+            .map_err(|_| BadAccessTokenEncoding)?;
+        Ok(Self::new(ps, token.expired))
+    }
+}
+
+/// Borrowed view of an encoded `TestAccessToken`
+#[derive(Debug)]
+pub struct TestAccessTokenRef<'a> {
+    policies: &'a [u8],
+    expired: bool,
+}
+
+impl<'a> PolicyAccessTokenRef<'a> for TestAccessTokenRef<'a> {
+    type Policy = TestPolicy;
+
+    fn policies(&self) -> PolicySetRef<'a, Self::Policy> {
+        PolicySetRef::from_bytes(self.policies)
+    }
+
+    fn expires_at(&self) -> Option<u64> {
+        Some(if self.expired { 0 } else { u64::MAX })
+    }
+
+    /// Reads the two fields of the protobuf message, which are shorter than 128 bytes
+    fn from_bytes(mut buf: &'a [u8]) -> Option<Self> {
+        let mut token = Self {
+            policies: &[],
+            expired: false,
+        };
+        while let [tag, value, rest @ ..] = buf {
+            match tag {
+                // expired = 1, varint
+                0x08 if *value < 0x80 => {
+                    token.expired = *value != 0;
+                    buf = rest;
+                }
+                // policies = 2, length delimited
+                0x12 if *value < 0x80 && rest.len() >= *value as usize => {
+                    let (policies, rest) = rest.split_at(*value as usize);
+                    token.policies = policies;
+                    buf = rest;
+                }
+                _ => return None,
+            }
+        }
+        if buf.is_empty() {
+            Some(token)
+        } else {
+            None
+        }
+    }
 }
@@ -1,38 +1,427 @@
+use std::collections::HashSet;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
-use crate::crypto::PublicKey;
+use crate::cose::CoseSign1;
+use crate::crypto::{DecryptionKey, Jwks, KeySet, PublicKey, SecretKey};
+use crate::encoding::decoded_len;
 use crate::error::Error::{self, *};
-use crate::message::SignedMessage;
+use crate::message::{
+    Envelope, MultiSignedMessage, SignedMessage, SignedMessageRef, SIGNATURE_SEPARATOR,
+};
+use crate::paseto::{PasetoMessage, SIGNATURE_LEN, V4_PUBLIC_HEADER};
 use crate::rbac::PolicyCond;
-use crate::token::{PolicyAccessToken, ToTokenStr};
+use crate::token::{
+    Clock, Limits, PolicyAccessToken, PolicyAccessTokenRef, ReplayCache, RevocationKey,
+    RevocationStore, SystemClock, ToTokenBytes, ToTokenStr,
+};
+
+/// Keys trusted by a validation authority, a validation authority works in exactly one mode
+enum TrustedKeys {
+    /// Tokens signed by identity server private keys
+    Public(KeySet),
+    /// Tokens tagged by a secret shared with the issuer
+    Secret(SecretKey),
+}
+
+/// Number of trusted keys which must have signed a token
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignaturePolicy {
+    /// Every key of the key set, in symmetric mode the secret key
+    All,
+    /// At least one trusted key, which is the default
+    Any,
+    /// At least this many distinct trusted keys, a key registered under several ids counts once
+    Threshold(usize),
+}
+
+impl SignaturePolicy {
+    /// `trusted` counts the distinct trusted keys, which only `All` needs
+    fn is_satisfied(self, verified: usize, trusted: impl FnOnce() -> usize) -> bool {
+        match self {
+            SignaturePolicy::All => verified > 0 && verified == trusted(),
+            SignaturePolicy::Any => verified > 0,
+            SignaturePolicy::Threshold(threshold) => verified > 0 && verified >= threshold,
+        }
+    }
+}
 
 pub struct ValidationAuthority<A> {
-    public_key: PublicKey,
+    keys: TrustedKeys,
+    signature_policy: SignaturePolicy,
+    limits: Limits,
+    /// Tokens must be encrypted to this key when it's set
+    decryption_key: Option<DecryptionKey>,
+    clock: Arc<dyn Clock>,
+    /// Seconds of clock skew tolerated between issuer and validator
+    leeway: u64,
+    revocation_store: Option<Arc<dyn RevocationStore>>,
+    replay_cache: Option<Arc<ReplayCache>>,
+    /// Accept messages of the legacy format, which have no header
+    accept_legacy: bool,
+    issuer: Option<String>,
+    audience: Option<String>,
     _p: PhantomData<A>,
 }
 
 impl<A: PolicyAccessToken> ValidationAuthority<A> {
     pub fn new(public_key: PublicKey) -> Self {
+        Self::with_key_set(KeySet::single(public_key))
+    }
+
+    /// Create validation authority which trusts every key in `key_set`
+    ///
+    /// Tokens carrying a key id are verified by the key registered under that id only.
+    /// Tokens without a key id are accepted if any key in the set verifies them.
+    pub fn with_key_set(key_set: KeySet) -> Self {
+        Self::with_keys(TrustedKeys::Public(key_set))
+    }
+
+    /// Create validation authority in symmetric mode, which accepts only tokens tagged by
+    /// `secret_key`
+    pub fn with_secret_key(secret_key: SecretKey) -> Self {
+        Self::with_keys(TrustedKeys::Secret(secret_key))
+    }
+
+    fn with_keys(keys: TrustedKeys) -> Self {
         Self {
-            public_key,
+            keys,
+            signature_policy: SignaturePolicy::Any,
+            limits: Limits::default(),
+            decryption_key: None,
+            clock: Arc::new(SystemClock),
+            leeway: 0,
+            revocation_store: None,
+            replay_cache: None,
+            accept_legacy: true,
+            issuer: None,
+            audience: None,
             _p: PhantomData,
         }
     }
 
-    fn decode_verify_check_expiration(&self, token: &str) -> Result<A, Error> {
-        // 1. decode signed message
-        let signed_message = SignedMessage::decode(token).ok_or(BadSignedMessageEncoding)?;
-        // 2. check if it is generated by trusted identity server
-        if !signed_message.verify(&self.public_key) {
+    /// Create validation authority trusting the keys of a JWKS document, e.g. a `jwks.json` file
+    pub fn from_jwks(json: &str) -> Result<Self, Error> {
+        KeySet::from_jwks(&Jwks::from_json(json)?).map(Self::with_key_set)
+    }
+
+    /// Accept only tokens encrypted to `decryption_key` by `SignedMessage::encrypt`
+    ///
+    /// Tokens are decrypted then verified by the trusted keys as usual, plain tokens are rejected.
+    /// Tokens must be signed for this key by `SignedMessage::create_for_recipient`, so a token
+    /// encrypted to another service can't be re-encrypted to this one.
+    pub fn decrypt_with(mut self, decryption_key: DecryptionKey) -> Self {
+        self.decryption_key = Some(decryption_key);
+        self
+    }
+
+    /// Reject tokens of the legacy `message.signature` format by
+    /// `Error::BadSignedMessageEncoding`, once every issuer signs tokens with a header
+    ///
+    /// Legacy tokens have no header, so no algorithm of the message is checked against the one of
+    /// the key and they can't be bound to associated data. They're accepted by default so tokens
+    /// issued before headers keep validating, which is deprecated and will change in the next
+    /// major version.
+    pub fn reject_legacy_tokens(mut self) -> Self {
+        self.accept_legacy = false;
+        self
+    }
+
+    /// Accept only tokens issued by `issuer`, others are rejected by `Error::UnexpectedIssuer`
+    pub fn expect_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Accept only tokens intended for `audience`, e.g. the URL of this service, others are
+    /// rejected by `Error::UnexpectedAudience`
+    ///
+    /// A token for several audiences is accepted when `audience` is one of them.
+    pub fn expect_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Require tokens to be signed by several trusted keys, see `MultiSignedMessage`
+    ///
+    /// A token signed by a single key only satisfies a policy which requires a single key.
+    pub fn require_signatures(mut self, signature_policy: SignaturePolicy) -> Self {
+        self.signature_policy = signature_policy;
+        self
+    }
+
+    /// Replace the default `Limits` on tokens
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Check expiration and not before time against `clock` instead of `SystemClock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Accept tokens expired or not valid yet by at most `seconds`, for clocks of issuer and
+    /// validator which are not quite in sync
+    pub fn with_leeway(mut self, seconds: u64) -> Self {
+        self.leeway = seconds;
+        self
+    }
+
+    /// Reject verified tokens whose id or subject is revoked in `revocation_store` by
+    /// `Error::Revoked`
+    pub fn check_revocation(mut self, revocation_store: Arc<dyn RevocationStore>) -> Self {
+        self.revocation_store = Some(revocation_store);
+        self
+    }
+
+    /// Accept every token once only, e.g. to confirm a payment, by recording its id in
+    /// `replay_cache`
+    ///
+    /// A token is recorded once it's authorized, by `enforce` when it satisfies the condition and
+    /// by `to_access_enforcer`, and its second presentation is rejected by `Error::Replayed`.
+    /// Tokens without id can't be told apart, they're rejected by `Error::Replayed` too. Ids
+    /// expire by the clock of the validation authority, see `with_clock`.
+    pub fn reject_replays(mut self, replay_cache: Arc<ReplayCache>) -> Self {
+        self.replay_cache = Some(replay_cache);
+        self
+    }
+
+    /// Trusted public keys, `None` in symmetric mode
+    pub fn key_set(&self) -> Option<&KeySet> {
+        match &self.keys {
+            TrustedKeys::Public(key_set) => Some(key_set),
+            TrustedKeys::Secret(_) => None,
+        }
+    }
+
+    fn verify(&self, envelope: &impl Envelope, associated_data: &[u8]) -> Result<(), Error> {
+        let verified = match (&self.keys, envelope.key_id()) {
+            (TrustedKeys::Public(key_set), Some(key_id)) => {
+                let key = key_set.get(key_id.as_ref()).ok_or(UnknownKeyId)?;
+                envelope.verify(key, associated_data)
+            }
+            (TrustedKeys::Public(key_set), None) => key_set
+                .values()
+                .any(|key| envelope.verify(key, associated_data)),
+            (TrustedKeys::Secret(secret_key), _) => {
+                envelope.verify_hmac(secret_key, associated_data)
+            }
+        };
+        if verified {
+            self.check_signature_policy(1)
+        } else {
+            Err(SignatureVerificationFail)
+        }
+    }
+
+    /// Every signature of a trusted key must be valid, signatures of unknown keys are ignored
+    fn verify_multi_signed(
+        &self,
+        message: &MultiSignedMessage,
+        associated_data: &[u8],
+    ) -> Result<(), Error> {
+        let verified = match &self.keys {
+            TrustedKeys::Public(key_set) => {
+                let mut verified = 0;
+                for (i, key_id) in message.key_ids().enumerate() {
+                    if let Some(key) = key_set.get(key_id) {
+                        if !message.verify_with_associated_data(key_id, key, associated_data) {
+                            return Err(SignatureVerificationFail);
+                        }
+                        // a key registered under several ids counts once, key ids are few so
+                        // they're rescanned rather than collected
+                        let counted = message.key_ids().take(i).any(|other| {
+                            key_set
+                                .get(other)
+                                .is_some_and(|other| other.as_bytes() == key.as_bytes())
+                        });
+                        if !counted {
+                            verified += 1;
+                        }
+                    }
+                }
+                verified
+            }
+            TrustedKeys::Secret(secret_key) => {
+                message.verify_hmac_with_associated_data(secret_key, associated_data) as usize
+            }
+        };
+        if verified == 0 {
             return Err(SignatureVerificationFail);
         }
+        self.check_signature_policy(verified)
+    }
+
+    fn check_legacy(&self, has_header: bool) -> Result<(), Error> {
+        if has_header || self.accept_legacy {
+            Ok(())
+        } else {
+            Err(BadSignedMessageEncoding)
+        }
+    }
+
+    fn check_signature_policy(&self, verified: usize) -> Result<(), Error> {
+        let trusted = || match &self.keys {
+            TrustedKeys::Public(key_set) => key_set
+                .values()
+                .map(PublicKey::as_bytes)
+                .collect::<HashSet<_>>()
+                .len(),
+            TrustedKeys::Secret(_) => 1,
+        };
+        if self.signature_policy.is_satisfied(verified, trusted) {
+            Ok(())
+        } else {
+            Err(InsufficientSignatures)
+        }
+    }
+
+    fn decode_verify_check_expiration(
+        &self,
+        token: &str,
+        associated_data: &[u8],
+    ) -> Result<A, Error> {
+        self.limits.check_token_len(token.len())?;
+        // 1. decrypt and decode signed message
+        match &self.decryption_key {
+            Some(decryption_key) => {
+                let message =
+                    SignedMessage::decrypt_with_limits(token, decryption_key, &self.limits)?;
+                self.verify_check_expiration(&message, associated_data)
+            }
+            None if token.contains(SIGNATURE_SEPARATOR) => {
+                let payload = token.split(SIGNATURE_SEPARATOR).next();
+                self.limits.check_payload_len(decoded_len(payload))?;
+                let message = MultiSignedMessage::decode(token).ok_or(BadSignedMessageEncoding)?;
+                self.verify_multi_signed(&message, associated_data)?;
+                self.check_expiration(message.message())
+            }
+            None if token.starts_with(V4_PUBLIC_HEADER) => {
+                // payload and signature are a single segment
+                let payload = token[V4_PUBLIC_HEADER.len()..].split('.').next();
+                self.limits
+                    .check_payload_len(decoded_len(payload).saturating_sub(SIGNATURE_LEN))?;
+                self.verify_check_expiration(
+                    &PasetoMessage::decode(token).ok_or(BadSignedMessageEncoding)?,
+                    associated_data,
+                )
+            }
+            None => {
+                // message is the segment before the signature
+                let payload = token.rsplit('.').nth(1);
+                self.limits.check_payload_len(decoded_len(payload))?;
+                let message = SignedMessage::decode(token).ok_or(BadSignedMessageEncoding)?;
+                self.check_legacy(message.header().is_some())?;
+                self.verify_check_expiration(&message, associated_data)
+            }
+        }
+    }
+
+    /// Binary tokens are COSE_Sign1, anything else must be a text token
+    fn decode_bytes_verify_check_expiration(&self, token: &[u8]) -> Result<A, Error> {
+        self.limits.check_token_len(token.len())?;
+        match CoseSign1::payload_len(token) {
+            // encrypted tokens are always text
+            Some(payload_len) if self.decryption_key.is_none() => {
+                self.limits.check_payload_len(payload_len)?;
+                let message = CoseSign1::decode(token).ok_or(BadSignedMessageEncoding)?;
+                self.verify_check_expiration(&message, &[])
+            }
+            _ => {
+                let token = std::str::from_utf8(token).map_err(|_| BadSignedMessageEncoding)?;
+                self.decode_verify_check_expiration(token, &[])
+            }
+        }
+    }
+
+    fn verify_check_expiration(
+        &self,
+        envelope: &impl Envelope,
+        associated_data: &[u8],
+    ) -> Result<A, Error> {
+        // 2. check if it is generated by trusted identity server
+        self.limits.check_payload_len(envelope.message().len())?;
+        self.verify(envelope, associated_data)?;
+        self.check_expiration(envelope.message())
+    }
+
+    fn check_expiration(&self, message: &[u8]) -> Result<A, Error> {
         // 3. extract access token from payload
-        let access_token = A::from_bytes(signed_message.message()).ok_or(BadAccessTokenEncoding)?;
-        // 4. check if it isn't expired
-        if access_token.is_expired() {
+        let access_token = A::from_bytes_with_limits(message, &self.limits)?;
+        // 4. check if it's valid now and not revoked
+        self.check_time(access_token.expires_at(), access_token.not_before())?;
+        self.check_claims(access_token.issuer(), |audience| {
+            access_token.has_audience(audience)
+        })?;
+        self.check_revocation_of(access_token.token_id(), access_token.subject())?;
+        Ok(access_token)
+    }
+
+    fn check_claims(
+        &self,
+        issuer: Option<&str>,
+        has_audience: impl Fn(&str) -> bool,
+    ) -> Result<(), Error> {
+        if self.issuer.is_some() && issuer != self.issuer.as_deref() {
+            Err(UnexpectedIssuer)
+        } else if self
+            .audience
+            .as_deref()
+            .is_some_and(|audience| !has_audience(audience))
+        {
+            Err(UnexpectedAudience)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_revocation_of(
+        &self,
+        token_id: Option<&str>,
+        subject: Option<&str>,
+    ) -> Result<(), Error> {
+        let store = match &self.revocation_store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        let revoked = token_id.is_some_and(|id| store.is_revoked(RevocationKey::TokenId(id)))
+            || subject.is_some_and(|subject| store.is_revoked(RevocationKey::Subject(subject)));
+        if revoked {
+            Err(Revoked)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_replay(&self, token_id: Option<&str>, expires_at: Option<u64>) -> Result<(), Error> {
+        let cache = match &self.replay_cache {
+            Some(cache) => cache,
+            None => return Ok(()),
+        };
+        let token_id = token_id.ok_or(Replayed)?;
+        // a token is valid until its expiration time and the leeway
+        let expires_at = expires_at.unwrap_or_default().saturating_add(self.leeway);
+        cache.check_and_record(token_id, expires_at, self.clock.now())
+    }
+
+    /// Record authorized token as used when replays are rejected
+    fn consume(&self, access_token: A) -> Result<A, Error> {
+        self.check_replay(access_token.token_id(), access_token.expires_at())?;
+        Ok(access_token)
+    }
+
+    fn check_time(&self, expires_at: Option<u64>, not_before: Option<u64>) -> Result<(), Error> {
+        let now = self.clock.now();
+        let expires_at = expires_at.ok_or(ExpiredAccessToken)?;
+        if now >= expires_at.saturating_add(self.leeway) {
             Err(ExpiredAccessToken)
+        } else if not_before.is_some_and(|not_before| now.saturating_add(self.leeway) < not_before)
+        {
+            Err(NotYetValidAccessToken)
         } else {
-            Ok(access_token)
+            Ok(())
         }
     }
 
@@ -40,9 +429,78 @@ impl<A: PolicyAccessToken> ValidationAuthority<A> {
         &self,
         condition: impl AsRef<PolicyCond<A::Policy>>,
         token: impl ToTokenStr,
+    ) -> Result<A, Error> {
+        self.enforce_in_context(condition, token, &[])
+    }
+
+    /// Same as `enforce` for a token bound to `associated_data`, context which the issuer signed
+    /// but which isn't part of the token, e.g. the audience hostname or a TLS channel binding
+    ///
+    /// A token bound to other data, or to none, is rejected by `Error::SignatureVerificationFail`.
+    pub fn enforce_in_context(
+        &self,
+        condition: impl AsRef<PolicyCond<A::Policy>>,
+        token: impl ToTokenStr,
+        associated_data: &[u8],
     ) -> Result<A, Error> {
         let token = token.to_token_str().ok_or(Unauthorized)?;
-        let access_token = self.decode_verify_check_expiration(token)?;
+        let access_token = self.decode_verify_check_expiration(token, associated_data)?;
+        self.consume(Self::check_condition(condition, access_token)?)
+    }
+
+    /// Same as `enforce` for a token in binary form, either COSE_Sign1 or the bytes of a text
+    /// token
+    pub fn enforce_bytes(
+        &self,
+        condition: impl AsRef<PolicyCond<A::Policy>>,
+        token: impl ToTokenBytes,
+    ) -> Result<A, Error> {
+        let token = token.to_token_bytes().ok_or(Unauthorized)?;
+        let access_token = self.decode_bytes_verify_check_expiration(token)?;
+        self.consume(Self::check_condition(condition, access_token)?)
+    }
+
+    /// Same as `enforce` without heap allocation, for plain `SignedMessage` tokens only
+    ///
+    /// The token is decoded into `buffer`, which is large enough when it's as long as the token,
+    /// and the returned access token borrows from it. See `SignedMessageRef`.
+    pub fn enforce_ref<'t, R>(
+        &self,
+        condition: impl AsRef<PolicyCond<A::Policy>>,
+        token: Option<&'t str>,
+        buffer: &'t mut [u8],
+    ) -> Result<R, Error>
+    where
+        R: PolicyAccessTokenRef<'t, Policy = A::Policy>,
+    {
+        let token = token.ok_or(Unauthorized)?;
+        self.limits.check_token_len(token.len())?;
+        // encrypted tokens are decrypted into a new allocation
+        if self.decryption_key.is_some() {
+            return Err(BadSignedMessageEncoding);
+        }
+        self.limits
+            .check_payload_len(decoded_len(token.rsplit('.').nth(1)))?;
+        let message = SignedMessageRef::decode(token, buffer).ok_or(BadSignedMessageEncoding)?;
+        self.check_legacy(message.header().is_some())?;
+        self.verify(&message, &[])?;
+        let access_token = R::from_bytes(message.message()).ok_or(BadAccessTokenEncoding)?;
+        self.check_time(access_token.expires_at(), access_token.not_before())?;
+        self.check_claims(access_token.issuer(), |audience| {
+            access_token.has_audience(audience)
+        })?;
+        self.check_revocation_of(access_token.token_id(), access_token.subject())?;
+        if !condition.as_ref().satisfy_ref(&access_token.policies()) {
+            return Err(Forbidden);
+        }
+        self.check_replay(access_token.token_id(), access_token.expires_at())?;
+        Ok(access_token)
+    }
+
+    fn check_condition(
+        condition: impl AsRef<PolicyCond<A::Policy>>,
+        access_token: A,
+    ) -> Result<A, Error> {
         // check if policies from access token satisfy required condition
         if condition.as_ref().satisfy(access_token.policies()) {
             Ok(access_token)
@@ -52,9 +510,28 @@ impl<A: PolicyAccessToken> ValidationAuthority<A> {
     }
 
     pub fn to_access_enforcer(&self, token: impl ToTokenStr) -> Result<AccessEnforcer<A>, Error> {
+        self.to_access_enforcer_in_context(token, &[])
+    }
+
+    /// Same as `to_access_enforcer` for a token bound to `associated_data`, see
+    /// `enforce_in_context`
+    pub fn to_access_enforcer_in_context(
+        &self,
+        token: impl ToTokenStr,
+        associated_data: &[u8],
+    ) -> Result<AccessEnforcer<A>, Error> {
         let token = token.to_token_str().ok_or(Unauthorized)?;
-        self.decode_verify_check_expiration(token)
-            .map(AccessEnforcer::new)
+        let access_token = self.decode_verify_check_expiration(token, associated_data)?;
+        self.consume(access_token).map(AccessEnforcer::new)
+    }
+
+    pub fn to_access_enforcer_bytes(
+        &self,
+        token: impl ToTokenBytes,
+    ) -> Result<AccessEnforcer<A>, Error> {
+        let token = token.to_token_bytes().ok_or(Unauthorized)?;
+        let access_token = self.decode_bytes_verify_check_expiration(token)?;
+        self.consume(access_token).map(AccessEnforcer::new)
     }
 }
 
@@ -94,16 +571,24 @@ macro_rules! assert_auth_error {
 
 #[cfg(test)]
 mod tests {
-    use crate::crypto::tests::{get_test_private_key, get_test_public_key};
-    use crate::crypto::PrivateKey;
-    use crate::rbac::test_helpers::TestPolicy::{Policy1, Policy2};
+    use crate::crypto::tests::{
+        ecdsa_test_keys, get_test_private_key, get_test_public_key, get_test_secret_key,
+    };
+    use crate::crypto::{PrivateKey, Signer};
+    use crate::rbac::test_helpers::TestPolicy::{self, Policy1, Policy2, Policy9};
     use crate::rbac::PolicyCond::*;
-    use crate::token::test_utils::TestAccessToken;
+    use crate::token::test_utils::{TestAccessToken, TestAccessTokenRef};
+    use crate::token::{
+        JwtAccessToken, ManualClock, MemoryRevocationStore, RevocationKey, StandardAccessToken,
+        TokenIssuer, DEFAULT_LIFETIME,
+    };
 
     use super::*;
 
     fn create_access_token_with_key(token: TestAccessToken, private_key: &PrivateKey) -> String {
-        SignedMessage::create(token.to_bytes(), &private_key).encode()
+        SignedMessage::create(token.to_bytes(), private_key)
+            .unwrap()
+            .encode()
     }
 
     fn create_access_token(token: TestAccessToken) -> String {
@@ -145,6 +630,753 @@ mod tests {
         assert_auth_error!(x, SignatureVerificationFail);
     }
 
+    #[test]
+    fn test_key_rotation() {
+        let old_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let new_key =
+            PrivateKey::from_base64("B1H3hDtRa0K0XxPC2tjD8uj2Tx3i9RlsQ7jSpl4OOIY").unwrap();
+        let key_set: KeySet = vec![
+            (
+                "old",
+                PublicKey::from_base64(&get_test_public_key()).unwrap(),
+            ),
+            (
+                "new",
+                PublicKey::from_base64("uneKfdOZUuupqMK7q1KwPFluM9zxpdIlyNntF4V1Dgs").unwrap(),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let va = ValidationAuthority::<TestAccessToken>::with_key_set(key_set);
+        let make_token = || TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+
+        // token issued before key ids were introduced
+        let token = create_access_token(TestAccessToken::new(vec![Policy1].into(), false));
+        assert!(va.enforce(NoCheck, Some(token)).is_ok());
+
+        let token = SignedMessage::create_with_key_id(make_token(), &old_key, "old")
+            .unwrap()
+            .encode();
+        assert!(va.enforce(NoCheck, Some(token)).is_ok());
+
+        let token = SignedMessage::create_with_key_id(make_token(), &new_key, "new")
+            .unwrap()
+            .encode();
+        assert!(va.enforce(NoCheck, Some(token)).is_ok());
+
+        let token = SignedMessage::create_with_key_id(make_token(), &new_key, "old")
+            .unwrap()
+            .encode();
+        let x = va.enforce(NoCheck, Some(token));
+        assert_auth_error!(x, SignatureVerificationFail);
+
+        let token = SignedMessage::create_with_key_id(make_token(), &new_key, "retired")
+            .unwrap()
+            .encode();
+        let x = va.enforce(NoCheck, Some(token));
+        assert_auth_error!(x, UnknownKeyId);
+    }
+
+    #[test]
+    fn test_thumbprint_key_id() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let token = TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+        let token =
+            SignedMessage::create_with_key_id(token, &private_key, private_key.thumbprint())
+                .unwrap()
+                .encode();
+        assert!(make_va().enforce(NoCheck, Some(token)).is_ok());
+    }
+
+    #[test]
+    fn test_mixed_algorithms() {
+        let (ecdsa_private_key, ecdsa_public_key) = ecdsa_test_keys();
+        let ed25519_private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let key_set: KeySet = vec![
+            (
+                "ed",
+                PublicKey::from_base64(&get_test_public_key()).unwrap(),
+            ),
+            ("ec", ecdsa_public_key),
+        ]
+        .into_iter()
+        .collect();
+        let va = ValidationAuthority::<TestAccessToken>::with_key_set(key_set);
+        let make_token = || TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+
+        let token =
+            SignedMessage::create_with_key_id(make_token(), &ecdsa_private_key, "ec").unwrap();
+        assert!(va.enforce(NoCheck, Some(token.encode())).is_ok());
+
+        // signature of one algorithm is never verified by key of other algorithm
+        let token =
+            SignedMessage::create_with_key_id(make_token(), &ed25519_private_key, "ec").unwrap();
+        let x = va.enforce(NoCheck, Some(token.encode()));
+        assert_auth_error!(x, SignatureVerificationFail);
+        let token =
+            SignedMessage::create_with_key_id(make_token(), &ecdsa_private_key, "ed").unwrap();
+        let x = va.enforce(NoCheck, Some(token.encode()));
+        assert_auth_error!(x, SignatureVerificationFail);
+    }
+
+    #[test]
+    fn test_symmetric_mode() {
+        let secret_key = SecretKey::from_base64(&get_test_secret_key()).unwrap();
+        let va = ValidationAuthority::<TestAccessToken>::with_secret_key(secret_key.clone());
+        let make_token = || TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+
+        let token = SignedMessage::create(make_token(), &secret_key)
+            .unwrap()
+            .encode();
+        assert!(va.enforce(Contains(Policy1), Some(token.clone())).is_ok());
+
+        // token of asymmetric mode is rejected by symmetric mode and vice versa
+        let x = va.enforce(
+            NoCheck,
+            Some(create_access_token(TestAccessToken::new(
+                vec![Policy1].into(),
+                false,
+            ))),
+        );
+        assert_auth_error!(x, SignatureVerificationFail);
+        let x = make_va().enforce(NoCheck, Some(token));
+        assert_auth_error!(x, SignatureVerificationFail);
+    }
+
+    #[test]
+    fn test_from_jwks() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let jwks = Jwks {
+            keys: vec![private_key.to_jwk(Some("k1"))],
+        };
+        // only public part is used for validation
+        let va = ValidationAuthority::<TestAccessToken>::from_jwks(&jwks.to_json()).unwrap();
+        let token = TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+        let token = SignedMessage::create_with_key_id(token, &private_key, "k1")
+            .unwrap()
+            .encode();
+        assert!(va.enforce(Contains(Policy1), Some(token)).is_ok());
+
+        // JWS of a JOSE issuer publishing the same JWKS
+        let token = TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+        let token = SignedMessage::create_jws(token, &private_key, Some("k1".to_owned()))
+            .unwrap()
+            .encode();
+        assert!(va.enforce(Contains(Policy1), Some(token)).is_ok());
+
+        let x = ValidationAuthority::<TestAccessToken>::from_jwks("[]").map(|_| ());
+        assert_auth_error!(x, BadKeyEncoding);
+    }
+
+    #[test]
+    fn test_jwt_claims() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let jwks = Jwks {
+            keys: vec![private_key.public_key().to_jwk(Some("k1"))],
+        };
+        let clock = Arc::new(ManualClock::new(1_600_000_000));
+        let va = ValidationAuthority::<JwtAccessToken<TestPolicy>>::from_jwks(&jwks.to_json())
+            .unwrap()
+            .with_clock(clock.clone());
+        // JWT of a JOSE issuer with claims tokidator doesn't know
+        let claims = br#"{"iss":"https://issuer.example.com","sub":"alice","aud":["api"],
+            "exp":1600000060,"scope":"read","policies":[1]}"#;
+        let token = SignedMessage::create_jws(claims.to_vec(), &private_key, Some("k1".to_owned()))
+            .unwrap()
+            .encode();
+
+        let access_token = va.enforce(Contains(Policy1), Some(token.as_str())).unwrap();
+        assert_eq!(access_token.sub.as_deref(), Some("alice"));
+        let x = va.enforce(Contains(Policy2), Some(token.as_str()));
+        assert_auth_error!(x, Forbidden);
+
+        let va = va.expect_issuer("https://issuer.example.com");
+        assert!(va.enforce(NoCheck, Some(token.as_str())).is_ok());
+        let va = va.expect_audience("api");
+        assert!(va.enforce(NoCheck, Some(token.as_str())).is_ok());
+
+        // token for another service
+        let claims = br#"{"iss":"https://issuer.example.com","aud":["billing","admin"],
+            "exp":1600000060,"policies":[1]}"#;
+        let foreign =
+            SignedMessage::create_jws(claims.to_vec(), &private_key, Some("k1".to_owned()))
+                .unwrap()
+                .encode();
+        let x = va.enforce(NoCheck, Some(foreign.as_str()));
+        assert_auth_error!(x, UnexpectedAudience);
+        // no audience at all
+        let claims = br#"{"iss":"https://issuer.example.com","exp":1600000060}"#;
+        let foreign =
+            SignedMessage::create_jws(claims.to_vec(), &private_key, Some("k1".to_owned()))
+                .unwrap()
+                .encode();
+        let x = va.enforce(NoCheck, Some(foreign.as_str()));
+        assert_auth_error!(x, UnexpectedAudience);
+
+        let va = va.expect_issuer("https://other.example.com");
+        let x = va.enforce(NoCheck, Some(token.as_str()));
+        assert_auth_error!(x, UnexpectedIssuer);
+
+        clock.advance(60);
+        let x = va.enforce(NoCheck, Some(token.as_str()));
+        assert_auth_error!(x, ExpiredAccessToken);
+    }
+
+    #[test]
+    fn test_encrypted_token() {
+        let decryption_key = DecryptionKey::generate();
+        let va = make_va().decrypt_with(decryption_key.clone());
+        let make_token = || TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let sign_for = |recipient: &DecryptionKey| {
+            SignedMessage::create_for_recipient(
+                make_token(),
+                &private_key,
+                None,
+                &recipient.public_key(),
+            )
+            .unwrap()
+        };
+
+        let signed_message = sign_for(&decryption_key);
+        let token = signed_message
+            .encrypt(&decryption_key.public_key())
+            .unwrap();
+        // payload can't be read from the token
+        assert!(!token.contains(&base64::encode_config(
+            make_token(),
+            base64::URL_SAFE_NO_PAD
+        )));
+        assert!(va.enforce(Contains(Policy1), Some(token)).is_ok());
+
+        let x = va.enforce(NoCheck, Some(signed_message.encode()));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+
+        let other_decryption_key = DecryptionKey::generate();
+        let token = sign_for(&other_decryption_key)
+            .encrypt(&other_decryption_key.public_key())
+            .unwrap();
+        let x = va.enforce(NoCheck, Some(token));
+        assert_auth_error!(x, DecryptionFail);
+
+        // message is only encrypted to the recipient it's signed for
+        let x = signed_message.encrypt(&other_decryption_key.public_key());
+        assert_auth_error!(x, EncryptionFail);
+        let unbound = SignedMessage::create(make_token(), &private_key).unwrap();
+        let x = unbound.encrypt(&decryption_key.public_key());
+        assert_auth_error!(x, EncryptionFail);
+
+        // recipient of a token re-encrypts it to another service
+        let other_va = make_va().decrypt_with(other_decryption_key.clone());
+        let reencrypt = |message: &SignedMessage, recipient: &DecryptionKey| {
+            let ciphertext = recipient
+                .public_key()
+                .encrypt(message.encode().as_bytes())
+                .unwrap();
+            base64::encode_config(ciphertext, base64::URL_SAFE_NO_PAD)
+        };
+        let x = other_va.enforce(
+            NoCheck,
+            Some(reencrypt(&signed_message, &other_decryption_key)),
+        );
+        assert_auth_error!(x, DecryptionFail);
+        let x = va.enforce(NoCheck, Some(reencrypt(&unbound, &decryption_key)));
+        assert_auth_error!(x, DecryptionFail);
+
+        // encrypted but signed by untrusted key
+        let other_key =
+            PrivateKey::from_base64("B1H3hDtRa0K0XxPC2tjD8uj2Tx3i9RlsQ7jSpl4OOIY").unwrap();
+        let token = SignedMessage::create_for_recipient(
+            make_token(),
+            &other_key,
+            None,
+            &decryption_key.public_key(),
+        )
+        .unwrap()
+        .encrypt(&decryption_key.public_key())
+        .unwrap();
+        let x = va.enforce(NoCheck, Some(token));
+        assert_auth_error!(x, SignatureVerificationFail);
+    }
+
+    #[test]
+    fn test_legacy_token() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let token = TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+        // `message.signature`, signing the message itself
+        let b64 = |bytes: &[u8]| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+        let legacy = format!(
+            "{}.{}",
+            b64(&token),
+            b64(&private_key.sign(&token).unwrap())
+        );
+
+        let va = make_va().reject_legacy_tokens();
+        let x = va.enforce(Contains(Policy1), Some(legacy.as_str()));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+        let mut buffer = [0u8; 256];
+        let x = va.enforce_ref::<TestAccessTokenRef>(NoCheck, Some(&legacy), &mut buffer);
+        assert_auth_error!(x, BadSignedMessageEncoding);
+
+        // already issued tokens validate by default
+        let va = make_va();
+        assert!(va.enforce(Contains(Policy1), Some(legacy.as_str())).is_ok());
+        let mut buffer = [0u8; 256];
+        assert!(va
+            .enforce_ref::<TestAccessTokenRef>(Contains(Policy1), Some(&legacy), &mut buffer)
+            .is_ok());
+        // tokens with header are still accepted
+        assert!(va
+            .enforce(
+                Contains(Policy1),
+                Some(create_access_token_with_key(
+                    TestAccessToken::new(vec![Policy1].into(), false),
+                    &private_key
+                ))
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_paseto_token() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let make_token = || TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+
+        let token = PasetoMessage::create(make_token(), vec![], &[], &private_key).unwrap();
+        assert!(make_va()
+            .enforce(Contains(Policy1), Some(token.encode()))
+            .is_ok());
+
+        let footer = format!(r#"{{"kid":"{}"}}"#, private_key.thumbprint()).into_bytes();
+        let token = PasetoMessage::create(make_token(), footer, &[], &private_key).unwrap();
+        assert!(make_va().enforce(NoCheck, Some(token.encode())).is_ok());
+
+        // implicit assertion isn't known by validation authority
+        let token = PasetoMessage::create(make_token(), vec![], b"implicit", &private_key).unwrap();
+        let x = make_va().enforce(NoCheck, Some(token.encode()));
+        assert_auth_error!(x, SignatureVerificationFail);
+
+        let x = make_va().enforce(NoCheck, Some("v4.public.AAAA"));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+    }
+
+    #[test]
+    fn test_associated_data() {
+        let va = make_va();
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let make_token = || TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+        let key_id = private_key.thumbprint();
+
+        let token = SignedMessage::create_with_associated_data(
+            make_token(),
+            &private_key,
+            Some(key_id.clone()),
+            b"api.example.com",
+        )
+        .unwrap()
+        .encode();
+        assert!(va
+            .enforce_in_context(Contains(Policy1), Some(token.as_str()), b"api.example.com")
+            .is_ok());
+        assert!(va
+            .to_access_enforcer_in_context(Some(token.as_str()), b"api.example.com")
+            .is_ok());
+        let x = va.enforce_in_context(NoCheck, Some(token.as_str()), b"other.example.com");
+        assert_auth_error!(x, SignatureVerificationFail);
+        let x = va.enforce(NoCheck, Some(token.as_str()));
+        assert_auth_error!(x, SignatureVerificationFail);
+        let x = va.enforce_in_context(Contains(Policy2), Some(token.as_str()), b"api.example.com");
+        assert_auth_error!(x, Forbidden);
+
+        // token issued without context
+        let token = create_access_token(TestAccessToken::new(vec![Policy1].into(), false));
+        let x = va.enforce_in_context(NoCheck, Some(token.as_str()), b"api.example.com");
+        assert_auth_error!(x, SignatureVerificationFail);
+
+        // implicit assertion of PASETO and every signature of a multi-signed token
+        let token = PasetoMessage::create(make_token(), vec![], b"ad", &private_key).unwrap();
+        assert!(va
+            .enforce_in_context(NoCheck, Some(token.encode()), b"ad")
+            .is_ok());
+        let mut message = MultiSignedMessage::new(make_token());
+        message
+            .sign_with_associated_data(&private_key, key_id, b"ad")
+            .unwrap();
+        assert!(va
+            .enforce_in_context(NoCheck, Some(message.encode()), b"ad")
+            .is_ok());
+        let x = va.enforce(NoCheck, Some(message.encode()));
+        assert_auth_error!(x, SignatureVerificationFail);
+    }
+
+    #[test]
+    fn test_cose_token() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let make_token = || TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+
+        let token = CoseSign1::create(make_token(), &private_key, None)
+            .unwrap()
+            .encode();
+        assert!(make_va()
+            .enforce_bytes(Contains(Policy1), Some(token.as_slice()))
+            .is_ok());
+        let x = make_va().enforce_bytes(Contains(Policy2), Some(token.as_slice()));
+        assert_auth_error!(x, Forbidden);
+
+        let token = CoseSign1::create(make_token(), &private_key, Some(private_key.thumbprint()))
+            .unwrap()
+            .encode();
+        assert!(make_va().to_access_enforcer_bytes(Some(token)).is_ok());
+
+        // text tokens are accepted as bytes too
+        let token = create_access_token(TestAccessToken::new(vec![Policy1].into(), false));
+        assert!(make_va()
+            .enforce_bytes(Contains(Policy1), Some(token.into_bytes()))
+            .is_ok());
+
+        let x = make_va().enforce_bytes(NoCheck, None::<Vec<u8>>);
+        assert_auth_error!(x, Unauthorized);
+        let x = make_va().enforce_bytes(NoCheck, Some(&[0xd2, 0x84, 0xff][..]));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+
+        // plain binary token is rejected when tokens must be encrypted
+        let token = CoseSign1::create(make_token(), &private_key, None)
+            .unwrap()
+            .encode();
+        let va = make_va().decrypt_with(DecryptionKey::generate());
+        let x = va.enforce_bytes(NoCheck, Some(token));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+    }
+
+    #[test]
+    fn test_signature_policy() {
+        let key1 = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let key2 = PrivateKey::from_base64("B1H3hDtRa0K0XxPC2tjD8uj2Tx3i9RlsQ7jSpl4OOIY").unwrap();
+        let (key3, public_key3) = ecdsa_test_keys();
+        let key_set: KeySet = vec![
+            ("k1", key1.public_key()),
+            ("k2", key2.public_key()),
+            ("k3", public_key3),
+        ]
+        .into_iter()
+        .collect();
+        let va = |policy| {
+            ValidationAuthority::<TestAccessToken>::with_key_set(key_set.clone())
+                .require_signatures(policy)
+        };
+        let sign = |keys: &[(&PrivateKey, &str)]| {
+            let token = TestAccessToken::new(vec![Policy1].into(), false).to_bytes();
+            let mut message = MultiSignedMessage::new(token);
+            for (key, key_id) in keys {
+                message.sign(*key, *key_id).unwrap();
+            }
+            message.encode()
+        };
+        let one = sign(&[(&key1, "k1")]);
+        let two = sign(&[(&key1, "k1"), (&key2, "k2")]);
+        let all = sign(&[(&key1, "k1"), (&key2, "k2"), (&key3, "k3")]);
+        let single = SignedMessage::create_with_key_id(
+            TestAccessToken::new(vec![Policy1].into(), false).to_bytes(),
+            &key1,
+            "k1",
+        )
+        .unwrap()
+        .encode();
+
+        let any = va(SignaturePolicy::Any);
+        assert!(any.enforce(Contains(Policy1), Some(one.as_str())).is_ok());
+        assert!(any
+            .enforce(Contains(Policy1), Some(single.as_str()))
+            .is_ok());
+        // co-signature lifted into a token of its own
+        let (message, signature) = one.split_once(SIGNATURE_SEPARATOR).unwrap();
+        let (header, signature) = signature.split_once('.').unwrap();
+        let lifted = [header, message, signature].join(".");
+        let x = any.enforce(NoCheck, Some(lifted.as_str()));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+
+        let threshold = va(SignaturePolicy::Threshold(2));
+        assert!(threshold
+            .enforce(Contains(Policy1), Some(two.as_str()))
+            .is_ok());
+        assert!(threshold.to_access_enforcer(Some(all.as_str())).is_ok());
+        let x = threshold.enforce(NoCheck, Some(one.as_str()));
+        assert_auth_error!(x, InsufficientSignatures);
+        let x = threshold.enforce(NoCheck, Some(single.as_str()));
+        assert_auth_error!(x, InsufficientSignatures);
+
+        let all_keys = va(SignaturePolicy::All);
+        assert!(all_keys.enforce(NoCheck, Some(all.as_str())).is_ok());
+        let x = all_keys.enforce(NoCheck, Some(two.as_str()));
+        assert_auth_error!(x, InsufficientSignatures);
+
+        // untrusted keys don't count
+        let other = PrivateKey::generate();
+        let x = threshold.enforce(NoCheck, Some(sign(&[(&key1, "k1"), (&other, "k9")])));
+        assert_auth_error!(x, InsufficientSignatures);
+        let x = threshold.enforce(NoCheck, Some(sign(&[(&other, "k9")])));
+        assert_auth_error!(x, SignatureVerificationFail);
+        // signature under the id of another key
+        let x = threshold.enforce(NoCheck, Some(sign(&[(&key1, "k1"), (&key1, "k2")])));
+        assert_auth_error!(x, SignatureVerificationFail);
+
+        // the same key under two ids is counted once
+        let mut key_set = key_set.clone();
+        key_set.insert("k4".to_owned(), key1.public_key());
+        let va = |policy| {
+            ValidationAuthority::<TestAccessToken>::with_key_set(key_set.clone())
+                .require_signatures(policy)
+        };
+        let x = va(SignaturePolicy::Threshold(2))
+            .enforce(NoCheck, Some(sign(&[(&key1, "k1"), (&key1, "k4")])));
+        assert_auth_error!(x, InsufficientSignatures);
+        assert!(va(SignaturePolicy::All)
+            .enforce(NoCheck, Some(all.as_str()))
+            .is_ok());
+
+        let x = any.enforce(NoCheck, Some("bWVzc2FnZQ~"));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+    }
+
+    #[test]
+    fn test_borrowed_token() {
+        let va = make_va();
+        let token = create_access_token(TestAccessToken::new(vec![Policy1].into(), false));
+        let mut buffer = [0u8; 256];
+        let access_token: TestAccessTokenRef = va
+            .enforce_ref(Contains(Policy1), Some(&token), &mut buffer)
+            .unwrap();
+        assert!(access_token.policies().contains(&Policy1));
+
+        let x = va.enforce_ref::<TestAccessTokenRef>(Contains(Policy2), Some(&token), &mut buffer);
+        assert_auth_error!(x, Forbidden);
+        let x = va.enforce_ref::<TestAccessTokenRef>(NoCheck, None, &mut buffer);
+        assert_auth_error!(x, Unauthorized);
+        let x = va.enforce_ref::<TestAccessTokenRef>(NoCheck, Some(&token), &mut buffer[..8]);
+        assert_auth_error!(x, BadSignedMessageEncoding);
+
+        let token = create_access_token(TestAccessToken::new(vec![Policy1].into(), true));
+        let x = va.enforce_ref::<TestAccessTokenRef>(NoCheck, Some(&token), &mut buffer);
+        assert_auth_error!(x, ExpiredAccessToken);
+
+        let other_key =
+            PrivateKey::from_base64("B1H3hDtRa0K0XxPC2tjD8uj2Tx3i9RlsQ7jSpl4OOIY").unwrap();
+        let token = create_access_token_with_key(
+            TestAccessToken::new(vec![Policy1].into(), false),
+            &other_key,
+        );
+        let x = va.enforce_ref::<TestAccessTokenRef>(NoCheck, Some(&token), &mut buffer);
+        assert_auth_error!(x, SignatureVerificationFail);
+    }
+
+    #[test]
+    fn test_limits() {
+        let make_token =
+            |policies: Vec<_>| create_access_token(TestAccessToken::new(policies.into(), false));
+        let token = make_token(vec![Policy1, Policy2]);
+        let limits = Limits {
+            max_token_len: token.len(),
+            max_payload_len: 8,
+            max_policy_len: 1,
+        };
+        let va = make_va().with_limits(limits);
+        assert!(va.enforce(Contains(Policy1), Some(token.as_str())).is_ok());
+
+        let x = va.enforce(NoCheck, Some(format!("{}A", token)));
+        assert_auth_error!(x, LimitExceeded);
+        let x = va.enforce_bytes(NoCheck, Some(format!("{}A", token).into_bytes()));
+        assert_auth_error!(x, LimitExceeded);
+        let mut buffer = [0u8; 256];
+        let padded = format!("{}A", token);
+        let x = va.enforce_ref::<TestAccessTokenRef>(NoCheck, Some(&padded), &mut buffer);
+        assert_auth_error!(x, LimitExceeded);
+
+        // payload over limit is rejected before its signature is verified
+        let va = make_va().with_limits(Limits {
+            max_payload_len: 2,
+            ..limits
+        });
+        let x = va.enforce(NoCheck, Some(token.as_str()));
+        assert_auth_error!(x, LimitExceeded);
+        let x = va.enforce(NoCheck, Some("AAAAAAAAAAAA.c2ln"));
+        assert_auth_error!(x, LimitExceeded);
+        // and before it's decoded
+        let x = va.enforce(NoCheck, Some("!!!!.c2ln"));
+        assert_auth_error!(x, LimitExceeded);
+        let x = va.enforce(NoCheck, Some("!!!!~e30.c2ln"));
+        assert_auth_error!(x, LimitExceeded);
+        let x = va.enforce(NoCheck, Some(format!("v4.public.{}", "!".repeat(90))));
+        assert_auth_error!(x, LimitExceeded);
+        // COSE_Sign1 of a 3 byte payload, which is truncated
+        let x = va.enforce_bytes(NoCheck, Some(&[0xd2, 0x84, 0x40, 0xa0, 0x43, 0x00][..]));
+        assert_auth_error!(x, LimitExceeded);
+        // and before it's decrypted, by its segment in the plaintext
+        let decryption_key = DecryptionKey::generate();
+        let token = SignedMessage::create_for_recipient(
+            TestAccessToken::new(vec![Policy1].into(), false).to_bytes(),
+            &PrivateKey::from_base64(&get_test_private_key()).unwrap(),
+            None,
+            &decryption_key.public_key(),
+        )
+        .unwrap()
+        .encrypt(&decryption_key.public_key())
+        .unwrap();
+        let encrypted_va = make_va().decrypt_with(decryption_key).with_limits(Limits {
+            max_token_len: token.len(),
+            ..Limits::default()
+        });
+        assert!(encrypted_va.enforce(NoCheck, Some(token.as_str())).is_ok());
+        let encrypted_va = encrypted_va.with_limits(Limits {
+            max_payload_len: 2,
+            ..limits
+        });
+        let x = encrypted_va.enforce(NoCheck, Some(token.as_str()));
+        assert_auth_error!(x, LimitExceeded);
+        // payload within limit
+        let x = va.enforce(NoCheck, Some("!!.c2ln"));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+        let x = va.enforce(NoCheck, Some(format!("v4.public.{}", "!".repeat(88))));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+        let x = va.enforce_bytes(NoCheck, Some(&[0xd2, 0x84, 0x40, 0xa0, 0x42, 0x00][..]));
+        assert_auth_error!(x, BadSignedMessageEncoding);
+
+        // Policy9 needs a second byte of policies
+        let token = make_token(vec![Policy9]);
+        let va = make_va().with_limits(Limits {
+            max_token_len: token.len(),
+            ..limits
+        });
+        let x = va.enforce(NoCheck, Some(token));
+        assert_auth_error!(x, LimitExceeded);
+    }
+
+    #[test]
+    fn test_clock() {
+        const NOW: u64 = 1_600_000_000;
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let clock = Arc::new(ManualClock::new(NOW));
+        let va =
+            ValidationAuthority::<StandardAccessToken<TestPolicy>>::new(private_key.public_key())
+                .with_clock(clock.clone());
+        let create_token = |exp: Option<u64>, nbf: Option<u64>| {
+            let mut token = StandardAccessToken::<TestPolicy>::new(vec![Policy1].into());
+            token.exp = exp;
+            token.nbf = nbf;
+            SignedMessage::create_with_key_id(
+                token.to_bytes(),
+                &private_key,
+                private_key.thumbprint(),
+            )
+            .unwrap()
+            .encode()
+        };
+
+        let token = create_token(Some(NOW + 60), Some(NOW - 60));
+        assert!(va.enforce(Contains(Policy1), Some(token.as_str())).is_ok());
+        clock.advance(60);
+        let x = va.enforce(NoCheck, Some(token.as_str()));
+        assert_auth_error!(x, ExpiredAccessToken);
+
+        let token = create_token(Some(NOW + 3600), Some(NOW + 120));
+        clock.set(NOW);
+        let x = va.enforce(NoCheck, Some(token.as_str()));
+        assert_auth_error!(x, NotYetValidAccessToken);
+
+        // token without expiration never gets valid
+        let x = va.enforce(NoCheck, Some(create_token(None, None)));
+        assert_auth_error!(x, ExpiredAccessToken);
+
+        // leeway covers skew in both directions
+        let va = va.with_leeway(120);
+        assert!(va.enforce(NoCheck, Some(token.as_str())).is_ok());
+        let token = create_token(Some(NOW - 119), None);
+        assert!(va.enforce(NoCheck, Some(token.as_str())).is_ok());
+        let token = create_token(Some(NOW - 120), None);
+        let x = va.enforce(NoCheck, Some(token.as_str()));
+        assert_auth_error!(x, ExpiredAccessToken);
+        let token = create_token(Some(u64::MAX), None);
+        assert!(va.enforce(NoCheck, Some(token.as_str())).is_ok());
+    }
+
+    #[test]
+    fn test_revocation() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let store = Arc::new(MemoryRevocationStore::new());
+        let va =
+            ValidationAuthority::<StandardAccessToken<TestPolicy>>::new(private_key.public_key())
+                .check_revocation(store.clone());
+        let issuer = TokenIssuer::new(private_key);
+        let issue = |jti: &str, sub: &str| {
+            let mut token = StandardAccessToken::<TestPolicy>::new(vec![Policy1].into());
+            token.jti = Some(jti.to_owned());
+            token.sub = Some(sub.to_owned());
+            issuer.issue(token).unwrap()
+        };
+
+        let t1 = issue("t1", "alice");
+        let t2 = issue("t2", "alice");
+        let t3 = issue("t3", "bob");
+        assert!(va
+            .enforce(Contains(Policy1), Some(t1.token.as_str()))
+            .is_ok());
+
+        store.revoke(RevocationKey::TokenId("t1"), t1.expires_at);
+        let x = va.enforce(NoCheck, Some(t1.token.as_str()));
+        assert_auth_error!(x, Revoked);
+        assert!(va.to_access_enforcer(Some(t2.token.as_str())).is_ok());
+
+        store.revoke(RevocationKey::Subject("alice"), t2.expires_at);
+        let x = va.to_access_enforcer(Some(t2.token.as_str())).map(|_| ());
+        assert_auth_error!(x, Revoked);
+        assert!(va.enforce(NoCheck, Some(t3.token.as_str())).is_ok());
+
+        // tokens without id and subject can't be revoked
+        let token = create_access_token(TestAccessToken::new(vec![Policy1].into(), false));
+        let va = make_va().check_revocation(store);
+        assert!(va.enforce(NoCheck, Some(token.as_str())).is_ok());
+    }
+
+    #[test]
+    fn test_replay() {
+        let private_key = PrivateKey::from_base64(&get_test_private_key()).unwrap();
+        let clock = Arc::new(ManualClock::new(1_600_000_000));
+        let cache = Arc::new(ReplayCache::new(16));
+        let va =
+            ValidationAuthority::<StandardAccessToken<TestPolicy>>::new(private_key.public_key())
+                .with_clock(clock.clone())
+                .reject_replays(cache.clone());
+        let issuer = TokenIssuer::new(private_key).with_clock(clock.clone());
+        let issue = |jti: Option<&str>| {
+            let mut token = StandardAccessToken::<TestPolicy>::new(vec![Policy1].into());
+            token.jti = jti.map(str::to_owned);
+            issuer.issue(token).unwrap().token
+        };
+
+        let token = issue(Some("t1"));
+        // forbidden token isn't used up
+        let x = va.enforce(Contains(Policy2), Some(token.as_str()));
+        assert_auth_error!(x, Forbidden);
+        assert!(va.enforce(Contains(Policy1), Some(token.as_str())).is_ok());
+        let x = va.enforce(Contains(Policy1), Some(token.as_str()));
+        assert_auth_error!(x, Replayed);
+        let x = va.to_access_enforcer(Some(token.as_str())).map(|_| ());
+        assert_auth_error!(x, Replayed);
+
+        let token = issue(Some("t2"));
+        assert!(va.to_access_enforcer(Some(token.as_str())).is_ok());
+        let x = va.enforce_bytes(NoCheck, Some(token.as_bytes()));
+        assert_auth_error!(x, Replayed);
+        assert_eq!(cache.len(), 2);
+
+        let x = va.enforce(NoCheck, Some(issue(None)));
+        assert_auth_error!(x, Replayed);
+
+        // an expired token is rejected before it's looked up
+        clock.advance(DEFAULT_LIFETIME);
+        let x = va.enforce(NoCheck, Some(token.as_str()));
+        assert_auth_error!(x, ExpiredAccessToken);
+    }
+
     #[test]
     fn test_access_token() {
         let va = make_va();